@@ -0,0 +1,269 @@
+extern crate advent2017;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process;
+use advent2017::day10::{Digest, KnotHasher};
+
+const USAGE: &str = "\
+usage: knothash [FILE]...
+       knothash -c [FILE]...
+
+Print the knot hash of each FILE, or of standard input if there are none
+or FILE is -. With -c, read `digest  filename` lines from the FILEs and
+check each file against its digest.";
+
+fn hash_reader<R: Read>(mut r: R) -> io::Result<Digest> {
+    let mut hasher = KnotHasher::new();
+    let mut buf = [0; 64 * 1024];
+    loop {
+        match r.read(&mut buf) {
+            Ok(0) => return Ok(hasher.finalize()),
+            Ok(n) => hasher.update(&buf[..n]),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn hash_file(name: &str) -> io::Result<Digest> {
+    if name == "-" {
+        let stdin = io::stdin();
+        let lock = stdin.lock();
+        hash_reader(lock)
+    } else {
+        hash_reader(File::open(name)?)
+    }
+}
+
+fn open_list(name: &str) -> io::Result<Box<dyn BufRead>> {
+    if name == "-" {
+        Ok(Box::new(BufReader::new(io::stdin())))
+    } else {
+        Ok(Box::new(BufReader::new(File::open(name)?)))
+    }
+}
+
+// A checksum line is `digest  name`, or `digest *name` as written by
+// tools in binary mode.
+fn parse_line(line: &str) -> Option<(Digest, &str)> {
+    let space = line.find(' ')?;
+    let (digest, rest) = line.split_at(space);
+    let digest: Digest = digest.parse().ok()?;
+    let name = rest.strip_prefix("  ").or_else(|| rest.strip_prefix(" *"))?;
+    if digest.len() == 16 && !name.is_empty() { Some((digest, name)) } else { None }
+}
+
+fn print_sums<W: Write, E: Write>(files: &[String], out: &mut W, err: &mut E)
+    -> io::Result<bool>
+{
+    let mut ok = true;
+    for name in files {
+        match hash_file(name) {
+            Ok(digest) => writeln!(out, "{}  {}", digest, name)?,
+            Err(e) => {
+                writeln!(err, "knothash: {}: {}", name, e)?;
+                ok = false;
+            },
+        }
+    }
+    Ok(ok)
+}
+
+fn check<W: Write, E: Write>(lists: &[String], out: &mut W, err: &mut E) -> io::Result<bool> {
+    let (mut bad_lines, mut unreadable, mut mismatched) = (0, 0, 0);
+    for list in lists {
+        let lines = match open_list(list) {
+            Ok(reader) => reader.lines(),
+            Err(e) => {
+                writeln!(err, "knothash: {}: {}", list, e)?;
+                unreadable += 1;
+                continue;
+            },
+        };
+        for line in lines {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    writeln!(err, "knothash: {}: {}", list, e)?;
+                    unreadable += 1;
+                    break;
+                },
+            };
+            let (expected, name) = match parse_line(&line) {
+                Some(entry) => entry,
+                None => {
+                    bad_lines += 1;
+                    continue;
+                },
+            };
+            match hash_file(name) {
+                Ok(digest) if digest == expected =>
+                    writeln!(out, "{}: OK", name)?,
+                Ok(_) => {
+                    writeln!(out, "{}: FAILED", name)?;
+                    mismatched += 1;
+                },
+                Err(e) => {
+                    writeln!(err, "knothash: {}: {}", name, e)?;
+                    writeln!(out, "{}: FAILED open or read", name)?;
+                    unreadable += 1;
+                },
+            }
+        }
+    }
+    if bad_lines > 0 {
+        writeln!(err, "knothash: WARNING: {} line(s) improperly formatted", bad_lines)?;
+    }
+    if unreadable > 0 {
+        writeln!(err, "knothash: WARNING: {} listed file(s) could not be read", unreadable)?;
+    }
+    if mismatched > 0 {
+        writeln!(err, "knothash: WARNING: {} computed checksum(s) did NOT match", mismatched)?;
+    }
+    Ok(bad_lines + unreadable + mismatched == 0)
+}
+
+// Everything but the process exit, which is returned as a status code.
+fn run<W: Write, E: Write>(args: &[String], out: &mut W, err: &mut E) -> i32 {
+    let mut files = Vec::new();
+    let mut check_mode = false;
+    for arg in args {
+        match &**arg {
+            "-c" | "--check" => check_mode = true,
+            "-h" | "--help" => {
+                let _ = writeln!(out, "{}", USAGE);
+                return 0;
+            },
+            _ if arg.starts_with('-') && arg != "-" => {
+                let _ = writeln!(err, "knothash: unknown option {}\n{}", arg, USAGE);
+                return 2;
+            },
+            _ => files.push(arg.clone()),
+        }
+    }
+    if files.is_empty() {
+        files.push(String::from("-"));
+    }
+
+    let ok = if check_mode { check(&files, out, err) } else { print_sums(&files, out, err) };
+    match ok.and_then(|ok| out.flush().map(|_| ok)) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            let _ = writeln!(err, "knothash: {}", e);
+            1
+        },
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let status = run(&args, &mut io::stdout().lock(), &mut io::stderr().lock());
+    if status != 0 {
+        process::exit(status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use advent2017::day10::knot_hash;
+
+    // A scratch file that is removed again when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &str) -> TempFile {
+            let path = env::temp_dir().join(format!("knothash-{}-{}", process::id(), name));
+            fs::write(&path, contents).unwrap();
+            TempFile(path)
+        }
+
+        fn name(&self) -> String {
+            self.0.to_str().unwrap().to_string()
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn run_with(args: &[&str]) -> (i32, String, String) {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let status = run(&args, &mut out, &mut err);
+        (status, String::from_utf8(out).unwrap(), String::from_utf8(err).unwrap())
+    }
+
+    #[test]
+    fn parsing() {
+        let digest = knot_hash("AoC 2017");
+        let hex = digest.to_string();
+        assert_eq!(Some((digest.clone(), "input.txt")), parse_line(&format!("{}  input.txt", hex)));
+        assert_eq!(Some((digest.clone(), "input.txt")), parse_line(&format!("{} *input.txt", hex)));
+        assert_eq!(Some((digest.clone(), "my file.txt")), parse_line(&format!("{}  my file.txt", hex)));
+        assert_eq!(Some((digest.clone(), " lead")), parse_line(&format!("{}   lead", hex)));
+
+        assert_eq!(None, parse_line(""));
+        assert_eq!(None, parse_line(&hex));
+        assert_eq!(None, parse_line(&format!("{}  ", hex)));
+        assert_eq!(None, parse_line(&format!("{} input.txt", hex)));
+        assert_eq!(None, parse_line(&format!("{}  input.txt", &hex[..30])));
+        assert_eq!(None, parse_line(&format!("{}00  input.txt", hex)));
+        assert_eq!(None, parse_line(&format!("{}  input.txt", hex.replace('a', "g"))));
+    }
+
+    #[test]
+    fn sums() {
+        let file = TempFile::new("sums data", "AoC 2017");
+        let (status, out, err) = run_with(&[&file.name()]);
+        assert_eq!((0, ""), (status, &*err));
+        assert_eq!(format!("{}  {}\n", knot_hash("AoC 2017"), file.name()), out);
+
+        let (status, _, err) = run_with(&[&file.name(), "/nonexistent/knothash"]);
+        assert_eq!(1, status);
+        assert!(err.starts_with("knothash: /nonexistent/knothash: "), "{}", err);
+    }
+
+    #[test]
+    fn checking() {
+        let good = TempFile::new("good file", "AoC 2017");
+        let bad = TempFile::new("bad", "changed");
+        let list = TempFile::new("good.sums", &format!("{}  {}\n", knot_hash("AoC 2017"), good.name()));
+        let (status, out, err) = run_with(&["-c", &list.name()]);
+        assert_eq!((0, format!("{}: OK\n", good.name()), String::new()), (status, out, err));
+
+        let missing = env::temp_dir().join("knothash-no-such-file");
+        let missing = missing.to_str().unwrap();
+        let list = TempFile::new("mixed.sums", &format!(
+            "{}  {}\n{} *{}\n{}  {}\nnot a checksum line\n",
+            knot_hash("AoC 2017"), good.name(),
+            knot_hash("original"), bad.name(),
+            knot_hash(""), missing));
+        let (status, out, err) = run_with(&["--check", &list.name()]);
+        assert_eq!(1, status);
+        assert_eq!(format!("{}: OK\n{}: FAILED\n{}: FAILED open or read\n",
+                           good.name(), bad.name(), missing), out);
+        let warnings: Vec<&str> = err.lines().filter(|l| l.contains("WARNING")).collect();
+        assert_eq!(warnings, vec![
+            "knothash: WARNING: 1 line(s) improperly formatted",
+            "knothash: WARNING: 1 listed file(s) could not be read",
+            "knothash: WARNING: 1 computed checksum(s) did NOT match",
+        ]);
+
+        let (status, out, _) = run_with(&["-c", "/nonexistent/knothash.sums"]);
+        assert_eq!((1, String::new()), (status, out));
+    }
+
+    #[test]
+    fn options() {
+        assert_eq!(0, run_with(&["-h"]).0);
+        assert_eq!(2, run_with(&["-x"]).0);
+    }
+}
@@ -33,6 +33,7 @@ use advent2017::day25;
 struct Config {
     target: String,
     input_file: String,
+    extra: Vec<String>,
 }
 
 impl Config {
@@ -46,7 +47,8 @@ impl Config {
             Some(arg) => arg,
             None => format!("inputs/{}.txt", target),
         };
-        Ok(Config { target, input_file })
+        let extra = args.collect();
+        Ok(Config { target, input_file, extra })
     }
 }
 
@@ -71,11 +73,36 @@ fn main() {
     match &*cfg.target {
         "day01" => day01::run(&input),
         "day02" => day02::run(&input),
+        "day03" if !cfg.extra.is_empty() =>
+            day03::run_render(&input, &cfg.extra).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            }),
         "day03" => day03::run(&input),
         "day04" => day04::run(&input),
+        "day05" if !cfg.extra.is_empty() =>
+            day05::run_rules(&input, &cfg.extra).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            }),
         "day05" => day05::run(&input),
+        "day06" if !cfg.extra.is_empty() =>
+            day06::run_mode(&input, &cfg.extra[0]).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            }),
         "day06" => day06::run(&input),
+        "day07" if !cfg.extra.is_empty() =>
+            day07::run_export(&input, &cfg.extra[0]).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            }),
         "day07" => day07::run(&input),
+        "day08" if !cfg.extra.is_empty() =>
+            day08::run_mode(&input, &cfg.extra[0]).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            }),
         "day08" => day08::run(&input),
         "day09" => day09::run(&input),
         "day10" => day10::run(&input),
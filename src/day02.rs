@@ -1,3 +1,6 @@
+use std::collections::HashSet;
+use std::fmt;
+
 fn parse_input(s: &str) -> Vec<Vec<u32>> {
     s.lines()
         .map(|line| {
@@ -8,34 +11,198 @@ fn parse_input(s: &str) -> Vec<Vec<u32>> {
         .collect()
 }
 
-fn solve(input: &str) -> (u32,u32) {
-    let sheet = parse_input(input);
-    let part1 = sheet.iter()
-        .map(|row| (row.iter().max().unwrap() - row.iter().min().unwrap()))
-        .sum();
-    let part2 = sheet.iter()
-        .map(|row| {
-            for i in 0..row.len()-1 {
-                for j in i+1..row.len() {
-                    let u = row[i];
-                    let v = row[j];
-                    if u % v == 0 {
-                        return u/v;
-                    } else if v % u == 0 {
-                        return v/u;
-                    }
+#[derive(Debug, PartialEq)]
+enum RowError {
+    NoPair { line: usize },
+    MultiplePairs { line: usize, pairs: Vec<(u32,u32)> },
+}
+
+// Most pairs shown when describing a row with too many.
+const LISTED_PAIRS: usize = 10;
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RowError::NoPair { line } =>
+                write!(f, "row {} has no evenly divisible pair", line),
+            RowError::MultiplePairs { line, ref pairs } => {
+                write!(f, "row {} has {} evenly divisible pairs: {:?}",
+                       line, pairs.len(), &pairs[..pairs.len().min(LISTED_PAIRS)])?;
+                if pairs.len() > LISTED_PAIRS {
+                    write!(f, " and {} more", pairs.len() - LISTED_PAIRS)?;
                 }
+                Ok(())
+            },
+        }
+    }
+}
+
+// Find every pair of values (u,v) in the row where v evenly divides u,
+// ordered by v and then u. Pairs are reported by value, so a repeated
+// value shows up once as (v,v). Zeros are ignored since they have no
+// sensible quotient.
+//
+// Values up to SIEVE_FACTOR times the number of distinct values are
+// "dense": each costs the smaller of its multiples up to the largest dense
+// value and the number of larger dense values, O(n log n) in all. The few
+// values above that are outliers. Pairs between outliers are found the
+// same way, and each outlier either tests every dense value or factors
+// itself by trial division and looks up its divisors, whichever is
+// cheaper. So no value costs more than a few thousand steps plus its
+// share of the sieve.
+fn divisible_pairs(row: &[u32]) -> Vec<(u32,u32)> {
+    let mut vals: Vec<u32> = row.iter().cloned().filter(|&v| v != 0).collect();
+    vals.sort();
+
+    let mut pairs = Vec::new();
+    let mut distinct = Vec::with_capacity(vals.len());
+    for v in vals {
+        if distinct.last() == Some(&v) {
+            if pairs.last() != Some(&(v,v)) {
+                pairs.push((v,v));
             }
-            0
-        })
-        .sum();
+        } else {
+            distinct.push(v);
+        }
+    }
+
+    let limit = SIEVE_FACTOR * distinct.len() as u64;
+    let split = distinct.partition_point(|&v| v as u64 <= limit);
+    let (dense, outliers) = distinct.split_at(split);
+
+    if let Some(&top) = dense.last() {
+        let mut present = vec![false; top as usize + 1];
+        for &v in dense {
+            present[v as usize] = true;
+        }
+        multiples(dense, top, |u| present[u as usize], &mut pairs);
+    }
+    if let Some(&top) = outliers.last() {
+        let present: HashSet<u32> = outliers.iter().cloned().collect();
+        multiples(outliers, top, |u| present.contains(&u), &mut pairs);
+
+        let primes = if dense.len() > PRIMES_BELOW_2_16 { small_primes() } else { Vec::new() };
+        let is_dense: HashSet<u32> = if primes.is_empty() {
+            HashSet::new()
+        } else {
+            dense.iter().cloned().collect()
+        };
+        for &u in outliers {
+            if primes.is_empty() {
+                pairs.extend(dense.iter().filter(|&&v| u % v == 0).map(|&v| (u, v)));
+            } else {
+                pairs.extend(divisors(u, &primes).into_iter()
+                    .filter(|d| is_dense.contains(d))
+                    .map(|d| (u, d)));
+            }
+        }
+    }
+    pairs.sort_by_key(|&(u, v)| (v, u));
+    pairs
+}
+
+const SIEVE_FACTOR: u64 = 64;
+const PRIMES_BELOW_2_16: usize = 6542;
+
+// Pairs within the sorted distinct `vals`, all at most `top`, where
+// `present` says whether a value is in `vals`.
+fn multiples<F: Fn(u32) -> bool>(vals: &[u32], top: u32, present: F, pairs: &mut Vec<(u32,u32)>) {
+    for (i, &v) in vals.iter().enumerate() {
+        let larger = &vals[i+1..];
+        if (top / v) as usize <= larger.len() {
+            // Fewer multiples than candidates: sieve over the multiples.
+            let mut m = 2 * v as u64;
+            while m <= top as u64 {
+                if present(m as u32) {
+                    pairs.push((m as u32, v));
+                }
+                m += v as u64;
+            }
+        } else {
+            // Fewer candidates than multiples: test each one.
+            pairs.extend(larger.iter().filter(|&&u| u % v == 0).map(|&u| (u, v)));
+        }
+    }
+}
+
+fn small_primes() -> Vec<u32> {
+    let mut composite = vec![false; 1 << 16];
+    let mut primes = Vec::with_capacity(PRIMES_BELOW_2_16);
+    for p in 2..composite.len() {
+        if !composite[p] {
+            primes.push(p as u32);
+            for m in (p * p..composite.len()).step_by(p) {
+                composite[m] = true;
+            }
+        }
+    }
+    primes
+}
+
+// The divisors of n below n, given every prime below 2^16.
+fn divisors(n: u32, primes: &[u32]) -> Vec<u32> {
+    let mut divs = vec![1];
+    let mut rest = n;
+    for &p in primes {
+        if (p as u64).pow(2) > rest as u64 {
+            break;
+        }
+        let mut pk = 1;
+        let len = divs.len();
+        while rest.is_multiple_of(p) {
+            rest /= p;
+            pk *= p;
+            for i in 0..len {
+                divs.push(divs[i] * pk);
+            }
+        }
+    }
+    if rest > 1 {
+        for i in 0..divs.len() {
+            divs.push(divs[i] * rest);
+        }
+    }
+    divs.retain(|&d| d != n);
+    divs
+}
+
+fn division_sum(sheet: &[Vec<u32>]) -> Result<u32, RowError> {
+    let mut sum = 0;
+    for (i, row) in sheet.iter().enumerate() {
+        let mut pairs = divisible_pairs(row);
+        match pairs.len() {
+            0 => return Err(RowError::NoPair { line: i + 1 }),
+            1 => {
+                let (u, v) = pairs.pop().unwrap();
+                sum += u / v;
+            },
+            _ => return Err(RowError::MultiplePairs { line: i + 1, pairs }),
+        }
+    }
+    Ok(sum)
+}
+
+fn checksum(sheet: &[Vec<u32>]) -> u32 {
+    sheet.iter()
+        .map(|row| (row.iter().max().unwrap() - row.iter().min().unwrap()))
+        .sum()
+}
+
+// Part 2 only makes sense if every row has exactly one divisible pair.
+fn solve(input: &str) -> (u32, Result<u32, RowError>) {
+    let sheet = parse_input(input);
+    let part1 = checksum(&sheet);
+    let part2 = division_sum(&sheet);
     (part1, part2)
 }
 
 pub fn run(input: &str) {
     let (part1, part2) = solve(input);
     println!("the checksum is {:?}", part1);
-    println!("the sum of each row's result is {:?}", part2);
+    match part2 {
+        Ok(sum) => println!("the sum of each row's result is {:?}", sum),
+        Err(e) => println!("there is no sum of each row's result: {}", e),
+    }
 }
 
 #[cfg(test)]
@@ -55,13 +222,89 @@ mod tests {
 
     #[test]
     fn example2() {
-        assert_eq!(9, solve("5 9 2 8\n9 4 7 3\n3 8 6 5\n").1);
+        assert_eq!(Ok(9), solve("5 9 2 8\n9 4 7 3\n3 8 6 5\n").1);
+    }
+
+    #[test]
+    fn pairs() {
+        assert_eq!(divisible_pairs(&[5, 9, 2, 8]), vec![(8, 2)]);
+        assert_eq!(divisible_pairs(&[3, 8, 6, 5]), vec![(6, 3)]);
+        assert_eq!(divisible_pairs(&[7, 7, 3, 0]), vec![(7, 7)]);
+        assert_eq!(divisible_pairs(&[2, 4, 12]), vec![(4, 2), (12, 2), (12, 4)]);
+        assert_eq!(divisible_pairs(&[5, 7, 11]), vec![]);
+    }
+
+    #[test]
+    fn wide_row() {
+        let mut row: Vec<u32> = (0..100_000).map(|i| 1_000_003 + 2 * i).collect();
+        row.push(2 * 1_000_003);
+        assert_eq!(divisible_pairs(&row), vec![(2_000_006, 1_000_003)]);
+
+        // small values next to outliers: 2..=100001 has 966,753 pairs, the
+        // prime outlier adds none and the primorial one per small divisor
+        let mut row: Vec<u32> = (2..=100_001).collect();
+        row.push(4_294_967_291);
+        row.push(2 * 3 * 5 * 7 * 11 * 13 * 17 * 19 * 23);
+        let pairs = divisible_pairs(&row);
+        assert_eq!(966_753 + 360, pairs.len());
+        assert!(pairs.iter().all(|&(u, v)| u != 4_294_967_291 && u % v == 0));
+    }
+
+    #[test]
+    fn outlier_pairs() {
+        // every way for outliers to pair up, checked against brute force
+        let brute = |row: &[u32]| {
+            let mut vals: Vec<u32> = row.to_vec();
+            vals.sort();
+            vals.dedup();
+            let mut pairs = Vec::new();
+            for &v in &vals {
+                if row.iter().filter(|&&x| x == v).count() > 1 {
+                    pairs.push((v, v));
+                }
+                pairs.extend(vals.iter().filter(|&&u| u > v && u % v == 0).map(|&u| (u, v)));
+            }
+            pairs.sort_by_key(|&(u, v)| (v, u));
+            pairs
+        };
+        let rows: Vec<Vec<u32>> = vec![
+            vec![3, 6, 600, 1_000_000, 3_000_000, 3_000_000, 4_000_000_000],
+            (1..=7000).chain(vec![720_720, 4_294_967_295, 65_521 * 65_521, 8_000_000]).collect(),
+            vec![4_294_967_295, 1_431_655_765, 858_993_459, 5, 3],
+        ];
+        for row in &rows {
+            assert_eq!(brute(row), divisible_pairs(row));
+        }
+    }
+
+    #[test]
+    fn row_errors() {
+        assert_eq!(division_sum(&parse_input("5 9 2 8\n5 7 11\n")),
+                   Err(RowError::NoPair { line: 2 }));
+        assert_eq!(division_sum(&parse_input("2 4 12\n")),
+                   Err(RowError::MultiplePairs {
+                       line: 1,
+                       pairs: vec![(4, 2), (12, 2), (12, 4)],
+                   }));
+        let row: Vec<String> = (1..100).map(|i| i.to_string()).collect();
+        let err = division_sum(&parse_input(&row.join(" "))).unwrap_err().to_string();
+        assert_eq!("row 1 has 374 evenly divisible pairs: [(2, 1), (3, 1), (4, 1), (5, 1), \
+                    (6, 1), (7, 1), (8, 1), (9, 1), (10, 1), (11, 1)] and 364 more", err);
+    }
+
+    #[test]
+    fn invalid_part2() {
+        // valid for part 1, but rows with several pairs or none
+        let (part1, part2) = solve("5 1 9 5\n7 5 3\n");
+        assert_eq!(12, part1);
+        assert!(part2.is_err());
     }
 
     #[test]
     fn real_input() {
         let input = include_str!("../inputs/day02.txt");
-        let x = solve(&input);
+        let (part1, part2) = solve(&input);
+        let x = (part1, part2.unwrap());
         assert_eq!(include_str!("../outputs/day02.txt"),
                    format!("{:?}", x));
     }
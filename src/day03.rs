@@ -1,79 +1,431 @@
-use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
 
-fn parse_input(s: &str) -> i32 {
+fn parse_input(s: &str) -> u64 {
     s.trim().parse().unwrap()
 }
 
-struct SpiralGen {
-    pos: (i32,i32),
-    dir: (i32,i32),
-    side: i32,
-    side_i: i32,
-    side_c: i32,
-}
+pub mod spiral {
+    pub type Coord = (i64,i64);
+
+    /// Walks the spiral from square 1 at the origin, heading right first
+    /// and turning counter-clockwise.
+    pub struct SpiralGen {
+        pos: Coord,
+        dir: Coord,
+        side: i64,
+        side_i: i64,
+        side_c: i64,
+    }
 
-impl SpiralGen {
-    fn new() -> SpiralGen {
-        SpiralGen {
-            pos: (0,0),
-            dir: (1,0),
-            side: 1,
-            side_i: 0,
-            side_c: 0,
+    impl SpiralGen {
+        pub fn new() -> SpiralGen {
+            SpiralGen {
+                pos: (0,0),
+                dir: (1,0),
+                side: 1,
+                side_i: 0,
+                side_c: 0,
+            }
         }
     }
+
+    impl Default for SpiralGen {
+        fn default() -> SpiralGen { SpiralGen::new() }
+    }
+
+    impl Iterator for SpiralGen {
+        type Item = Coord;
+        fn next(&mut self) -> Option<Self::Item> {
+            let p = self.pos;
+            self.pos.0 += self.dir.0;
+            self.pos.1 += self.dir.1;
+            self.side_i += 1;
+            if self.side_i >= self.side {
+                self.side_i = 0;
+                self.dir = (-self.dir.1, self.dir.0);
+                self.side_c += 1;
+                if self.side_c >= 2 {
+                    self.side_c = 0;
+                    self.side += 1;
+                }
+            }
+            Some(p)
+        }
+    }
+
+    /// Position of square `index`, where square 1 is the origin.
+    pub fn index_to_coord(index: u64) -> Coord {
+        assert!(index > 0, "spiral squares are numbered from 1");
+        if index == 1 {
+            return (0,0);
+        }
+
+        // Ring k holds squares (2k-1)^2+1 ..= (2k+1)^2, starting just
+        // above the bottom right corner.
+        let k = (index - 1).isqrt().div_ceil(2) as i64;
+        let t = (index - (2*k as u64 - 1).pow(2) - 1) as i64;
+        let side = 2*k;
+        match t / side {
+            0 => (k, -k + 1 + t),
+            1 => (k - 1 - (t - side), k),
+            2 => (-k, k - 1 - (t - 2*side)),
+            _ => (-k + 1 + (t - 3*side), -k),
+        }
+    }
+
+    /// Square number at `pos`, or None if it does not fit in a `u64`.
+    pub fn coord_to_index(pos: Coord) -> Option<u64> {
+        let (x, y) = pos;
+        let k = x.checked_abs()?.max(y.checked_abs()?);
+        if k == 0 {
+            return Some(1);
+        }
+
+        let side = k.checked_mul(2)?;
+        let t = if x == k && y > -k {
+            y + k - 1
+        } else if y == k {
+            side.checked_add(k - 1 - x)?
+        } else if x == -k {
+            side.checked_mul(2)?.checked_add(k - 1 - y)?
+        } else {
+            side.checked_mul(3)?.checked_add(x + k - 1)?
+        };
+        (side as u64 - 1).checked_mul(side as u64 - 1)
+            .and_then(|base| base.checked_add(t as u64 + 1))
+    }
+
+    /// Manhattan distance from square `index` to square 1.
+    pub fn distance(index: u64) -> u64 {
+        let (x, y) = index_to_coord(index);
+        x.unsigned_abs() + y.unsigned_abs()
+    }
 }
 
-impl Iterator for SpiralGen {
-    type Item = (i32,i32);
-    fn next(&mut self) -> Option<Self::Item> {
-        let p = self.pos;
-        self.pos.0 += self.dir.0;
-        self.pos.1 += self.dir.1;
-        self.side_i += 1;
-        if self.side_i >= self.side {
-            self.side_i = 0;
-            self.dir = (-self.dir.1, self.dir.0);
-            self.side_c += 1;
-            if self.side_c >= 2 {
-                self.side_c = 0;
-                self.side += 1;
+use self::spiral::*;
+
+/// Which already-written squares feed into a new square's value.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Neighborhood {
+    /// Squares within Manhattan distance r.
+    VonNeumann(i64),
+    /// Squares within Chebyshev distance r (r = 1 is the puzzle's 3x3).
+    Moore(i64),
+}
+
+impl Neighborhood {
+    fn offsets(&self) -> Vec<Coord> {
+        let (r, in_range): (i64, fn(i64,i64,i64) -> bool) = match *self {
+            Neighborhood::VonNeumann(r) => (r, |dx,dy,r| dx.abs() + dy.abs() <= r),
+            Neighborhood::Moore(r) => (r, |_,_,_| true),
+        };
+        let mut offsets = Vec::new();
+        for dx in -r..r+1 {
+            for dy in -r..r+1 {
+                if (dx,dy) != (0,0) && in_range(dx, dy, r) {
+                    offsets.push((dx,dy));
+                }
             }
         }
-        Some(p)
+        offsets
+    }
+}
+
+/// How neighbouring values are combined.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Aggregate {
+    Sum,
+    /// Product of the written neighbours, reduced modulo m. Products can
+    /// settle into a fixed point (all 1s, or 5s modulo 10), so searches
+    /// stop after `PRODUCT_SQUARES` squares unless given a limit.
+    ProductMod(u128),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StressError {
+    /// The value for this square does not fit in a u128.
+    Overflow { square: u64 },
+    /// The generator stopped after this many squares without finding a value.
+    Exhausted { squares: u64 },
+    /// Every value is below `bound`, so nothing can exceed the key.
+    Unreachable { key: u128, bound: u128 },
+    /// `Aggregate::ProductMod(0)` has no values to reduce into.
+    ZeroModulus,
+}
+
+impl fmt::Display for StressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StressError::Overflow { square } =>
+                write!(f, "value overflows at square {}", square),
+            StressError::Exhausted { squares } =>
+                write!(f, "no value found in the first {} squares", squares),
+            StressError::Unreachable { key, bound } =>
+                write!(f, "values are always below {}, so none exceed {}", bound, key),
+            StressError::ZeroModulus =>
+                write!(f, "product modulus must be non-zero"),
+        }
     }
 }
 
-fn sum_around(grid: &HashMap<(i32,i32),i32>, pos: (i32,i32)) -> i32 {
-    let mut sum = 0;
-    for i in pos.0-1..pos.0+2 {
-        for j in pos.1-1..pos.1+2 {
-            sum += *grid.get(&(i,j)).unwrap_or(&0);
+/// Default square limit when searching `ProductMod` values.
+pub const PRODUCT_SQUARES: u64 = 1 << 20;
+
+/// Generates the stress-test values written into each square in spiral
+/// order, starting with `seed` at square 1. Values are kept in a dense
+/// buffer indexed by square number.
+pub struct StressTest {
+    offsets: Vec<Coord>,
+    aggregate: Aggregate,
+    seed: u128,
+    limit: Option<u64>,
+    values: Vec<u128>,
+    failed: bool,
+}
+
+impl StressTest {
+    pub fn new(neighborhood: Neighborhood, aggregate: Aggregate) -> StressTest {
+        StressTest {
+            offsets: neighborhood.offsets(),
+            aggregate,
+            seed: 1,
+            limit: None,
+            values: Vec::new(),
+            failed: false,
+        }
+    }
+
+    /// The generator used by the puzzle: 3x3 sums starting from 1.
+    pub fn puzzle() -> StressTest {
+        StressTest::new(Neighborhood::Moore(1), Aggregate::Sum)
+    }
+
+    pub fn with_seed(mut self, seed: u128) -> StressTest {
+        self.seed = seed;
+        self
+    }
+
+    /// Stop after this many squares.
+    pub fn with_limit(mut self, squares: u64) -> StressTest {
+        self.limit = Some(squares);
+        self
+    }
+
+    /// Values written so far; square n is at index n - 1.
+    pub fn values(&self) -> &[u128] {
+        &self.values
+    }
+
+    /// The largest value ever written, when every value after the seed is
+    /// fixed: with no neighbours each square gets the empty sum or product,
+    /// and a zero seed makes every later value zero.
+    fn stationary_max(&self) -> Option<u128> {
+        if self.offsets.is_empty() {
+            let empty = match self.aggregate {
+                Aggregate::Sum => 0,
+                Aggregate::ProductMod(m) => 1 % m,
+            };
+            Some(self.seed.max(empty))
+        } else if self.seed == 0 {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    fn next_value(&self) -> Option<u128> {
+        let square = self.values.len() as u64 + 1;
+        if square == 1 {
+            return Some(self.seed);
+        }
+        let pos = index_to_coord(square);
+        let mut neighbors = self.offsets.iter()
+            .filter_map(|&(dx,dy)| coord_to_index((pos.0 + dx, pos.1 + dy)))
+            .filter(|&i| i < square)
+            .map(|i| self.values[i as usize - 1]);
+        match self.aggregate {
+            Aggregate::Sum =>
+                neighbors.try_fold(0, |acc: u128, x| acc.checked_add(x)),
+            Aggregate::ProductMod(m) =>
+                Some(neighbors.fold(1 % m, |acc, x| mul_mod(acc, x, m))),
+        }
+    }
+}
+
+// a * b mod m without overflowing, for any non-zero m.
+fn mul_mod(a: u128, b: u128, m: u128) -> u128 {
+    if let Some(p) = a.checked_mul(b) {
+        return p % m;
+    }
+    // a + b mod m, for a, b < m
+    let add = |a: u128, b: u128| if a >= m - b { a - (m - b) } else { a + b };
+    let (mut a, mut b) = (a % m, b % m);
+    let mut p = 0;
+    while b > 0 {
+        if b & 1 == 1 {
+            p = add(p, a);
+        }
+        a = add(a, a);
+        b >>= 1;
+    }
+    p
+}
+
+impl Iterator for StressTest {
+    type Item = Result<u128, StressError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let square = self.values.len() as u64 + 1;
+        if self.failed || self.limit.is_some_and(|n| square > n) {
+            return None;
+        }
+        if square > 1 && self.aggregate == Aggregate::ProductMod(0) {
+            self.failed = true;
+            return Some(Err(StressError::ZeroModulus));
+        }
+        match self.next_value() {
+            Some(x) => {
+                self.values.push(x);
+                Some(Ok(x))
+            },
+            None => {
+                self.failed = true;
+                Some(Err(StressError::Overflow { square }))
+            },
         }
     }
-    sum
 }
 
-fn find_first_gt(key: i32) -> i32 {
-    let mut spiral = SpiralGen::new();
-    let mut grid: HashMap<(i32,i32),i32> = HashMap::new();
-    grid.insert((0,0), 1);
-    spiral.next(); // discard (0,0)
-    for pos in spiral {
-        let x = sum_around(&grid, pos);
+fn find_first_gt(mut test: StressTest, key: u128) -> Result<u128, StressError> {
+    if test.aggregate == Aggregate::ProductMod(0) {
+        return Err(StressError::ZeroModulus);
+    }
+    if let Some(max) = test.stationary_max() {
+        if key >= max {
+            return Err(StressError::Unreachable { key, bound: max.saturating_add(1) });
+        }
+    }
+    if let Aggregate::ProductMod(m) = test.aggregate {
+        if key.saturating_add(1) >= m && key >= test.seed {
+            return Err(StressError::Unreachable { key, bound: m });
+        }
+        test.limit = test.limit.or(Some(PRODUCT_SQUARES));
+    }
+    for x in test.by_ref() {
+        let x = x?;
         if x > key {
-            return x;
+            return Ok(x);
         }
-        grid.insert(pos, x);
     }
-    0
+    Err(StressError::Exhausted { squares: test.values.len() as u64 })
 }
 
-fn solve(input: &str) -> (i32,i32) {
+/// Text and image views of the spiral, for eyeballing spiral variants.
+pub mod render {
+    use std::collections::HashSet;
+    use super::spiral::*;
+    use super::StressTest;
+
+    /// The squares on a shortest path from square `index` to the origin,
+    /// moving along x first and then along y.
+    pub fn manhattan_path(index: u64) -> Vec<Coord> {
+        let (mut x, mut y) = index_to_coord(index);
+        let mut path = vec![(x,y)];
+        while x != 0 {
+            x -= x.signum();
+            path.push((x,y));
+        }
+        while y != 0 {
+            y -= y.signum();
+            path.push((x,y));
+        }
+        path
+    }
+
+    /// The first values a stress test writes, enough to fill `radius`.
+    /// Stops early if a value overflows.
+    pub fn stress_values(test: StressTest, radius: i64) -> Vec<u128> {
+        let squares = (2*radius + 1).pow(2) as usize;
+        test.take(squares).map_while(Result::ok).collect()
+    }
+
+    /// Lays out the squares within `radius` of the origin as a table with
+    /// +y at the top. `label` gives the text for a square number. Squares
+    /// on the path from `highlight` to the origin are shown in brackets.
+    pub fn ascii<F>(radius: i64, label: F, highlight: Option<u64>) -> String where
+        F: Fn(u64) -> String
+    {
+        let path: HashSet<Coord> = highlight.map(manhattan_path)
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        let rows: Vec<(i64, Vec<String>)> = (-radius..radius+1).rev()
+            .map(|y| {
+                let cells = (-radius..radius+1)
+                    .map(|x| label(coord_to_index((x,y)).expect("radius too large")))
+                    .collect();
+                (y, cells)
+            })
+            .collect();
+        let width = rows.iter()
+            .flat_map(|(_, cells)| cells.iter().map(|c| c.len()))
+            .max()
+            .unwrap_or(0);
+
+        let mut out = String::new();
+        for (y, cells) in rows {
+            let mut line = String::new();
+            for (x, cell) in (-radius..radius+1).zip(cells) {
+                if path.contains(&(x,y)) {
+                    line += &format!("[{:>w$}]", cell, w = width);
+                } else {
+                    line += &format!(" {:>w$} ", cell, w = width);
+                }
+            }
+            out += line.trim_end();
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Renders the squares within `radius` as a binary PPM image with each
+    /// square drawn `scale` pixels wide. `shade` gives the grey level for a
+    /// square number; the path from `highlight` to the origin is red.
+    pub fn ppm<F>(radius: i64, scale: usize, shade: F, highlight: Option<u64>) -> Vec<u8> where
+        F: Fn(u64) -> u8
+    {
+        let path: HashSet<Coord> = highlight.map(manhattan_path)
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        let side = (2*radius + 1) as usize * scale;
+        let mut img = format!("P6\n{} {}\n255\n", side, side).into_bytes();
+        for y in (-radius..radius+1).rev() {
+            let mut row = Vec::with_capacity(side * 3);
+            for x in -radius..radius+1 {
+                let rgb = if path.contains(&(x,y)) {
+                    [255, 0, 0]
+                } else {
+                    let g = shade(coord_to_index((x,y)).expect("radius too large"));
+                    [g, g, g]
+                };
+                for _ in 0..scale {
+                    row.extend_from_slice(&rgb);
+                }
+            }
+            for _ in 0..scale {
+                img.extend_from_slice(&row);
+            }
+        }
+        img
+    }
+}
+
+fn solve(input: &str) -> (u64,u128) {
     let key = parse_input(input);
-    let pos = SpiralGen::new().nth(key as usize - 1).unwrap();
-    let part1 = pos.0.abs() + pos.1.abs();
-    let part2 = find_first_gt(key);
+    let part1 = distance(key);
+    let part2 = find_first_gt(StressTest::puzzle(), key as u128)
+        .unwrap_or_else(|e| panic!("{}", e));
     (part1, part2)
 }
 
@@ -83,6 +435,49 @@ pub fn run(input: &str) {
     println!("the solution to part 2 is {:?}", part2);
 }
 
+/// Draws the spiral around the input square instead of solving:
+/// `ascii` labels squares by number, `stress` by the puzzle's stress-test
+/// values, and `ppm` writes an image to stdout. An optional radius (and,
+/// for `ppm`, a pixel scale) may follow; the radius defaults to the ring
+/// holding the input square, whose path to the origin is highlighted.
+pub fn run_render(input: &str, args: &[String]) -> Result<(), String> {
+    let key = input.trim().parse::<u64>()
+        .map_err(|e| format!("bad input square: {}", e))?;
+    let (x, y) = index_to_coord(key);
+    let number = |i: usize, default: i64| -> Result<i64, String> {
+        match args.get(i) {
+            Some(arg) => arg.parse().map_err(|e| format!("bad number '{}': {}", arg, e)),
+            None => Ok(default),
+        }
+    };
+    let radius = number(1, x.abs().max(y.abs()))?;
+    if radius < 0 || coord_to_index((radius, -radius)).is_none() {
+        return Err(format!("radius {} is out of range", radius));
+    }
+    match args.first().map(|s| &s[..]) {
+        Some("ascii") => print!("{}", render::ascii(radius, |i| i.to_string(), Some(key))),
+        Some("stress") => {
+            let values = render::stress_values(StressTest::puzzle(), radius);
+            let label = |i: u64| values.get(i as usize - 1)
+                .map_or_else(|| String::from("?"), |v| v.to_string());
+            print!("{}", render::ascii(radius, label, Some(key)));
+        },
+        Some("ppm") => {
+            let scale = number(2, 4)?;
+            if scale < 1 {
+                return Err(format!("scale {} must be positive", scale));
+            }
+            let last = (2*radius + 1).pow(2) as u64;
+            let shade = |i: u64| (255 - 255 * (i - 1) / last) as u8;
+            let img = render::ppm(radius, scale as usize, shade, Some(key));
+            io::stdout().write_all(&img).map_err(|e| e.to_string())?;
+        },
+        Some(mode) => return Err(format!("unknown render mode '{}' (expected ascii, stress or ppm)", mode)),
+        None => return Err(String::from("missing render mode")),
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -100,6 +495,159 @@ mod tests {
         assert_eq!(31, solve("1024").0);
     }
 
+    #[test]
+    fn closed_form() {
+        for (i, pos) in SpiralGen::new().take(10_000).enumerate() {
+            let index = i as u64 + 1;
+            assert_eq!(index_to_coord(index), pos);
+            assert_eq!(coord_to_index(pos), Some(index));
+        }
+    }
+
+    #[test]
+    fn huge_indices() {
+        let n = 1_000_000_000_000_000_000;
+        assert_eq!(coord_to_index(index_to_coord(n)), Some(n));
+        assert_eq!(index_to_coord(u64::MAX), (-2147483646, 2147483648));
+        assert_eq!(coord_to_index((2147483648, -2147483647)), Some(18446744065119617026));
+        assert_eq!(coord_to_index((2147483648, -2147483648)), None);
+        assert_eq!(coord_to_index((i64::MIN, 0)), None);
+        assert_eq!(coord_to_index((0, i64::MAX)), None);
+        assert_eq!(coord_to_index((i64::MAX / 2, 3)), None);
+        assert_eq!(distance(999_999_999_999_999_999), 999_999_998);
+    }
+
+    #[test]
+    fn example2() {
+        let values: Vec<u128> = StressTest::puzzle().take(23)
+            .map(|x| x.unwrap())
+            .collect();
+        assert_eq!(values, vec![1, 1, 2, 4, 5, 10, 11, 23, 25, 26, 54, 57, 59,
+                                122, 133, 142, 147, 304, 330, 351, 362, 747, 806]);
+        assert_eq!(Ok(747), find_first_gt(StressTest::puzzle(), 400));
+    }
+
+    #[test]
+    fn neighborhoods() {
+        assert_eq!(Neighborhood::VonNeumann(1).offsets().len(), 4);
+        assert_eq!(Neighborhood::VonNeumann(2).offsets().len(), 12);
+        assert_eq!(Neighborhood::Moore(2).offsets().len(), 24);
+
+        let values: Vec<u128> = StressTest::new(Neighborhood::VonNeumann(1), Aggregate::Sum)
+            .take(9)
+            .map(|x| x.unwrap())
+            .collect();
+        assert_eq!(values, vec![1, 1, 1, 2, 2, 3, 3, 4, 5]);
+    }
+
+    #[test]
+    fn product() {
+        let test = StressTest::new(Neighborhood::Moore(1), Aggregate::ProductMod(1000))
+            .with_seed(2);
+        let values: Vec<u128> = test.take(6).map(|x| x.unwrap()).collect();
+        assert_eq!(values, vec![2, 2, 4, 16, 32, 24]);
+
+        // moduli past 2^64: 2^140 = -2^13 mod 2^127 + 1
+        let m = (1 << 127) + 1;
+        let test = StressTest::new(Neighborhood::Moore(1), Aggregate::ProductMod(m))
+            .with_seed(1 << 70);
+        let values: Vec<u128> = test.take(3).map(|x| x.unwrap()).collect();
+        assert_eq!(values, vec![1 << 70, 1 << 70, m - 8192]);
+        assert_eq!(6, mul_mod(u128::MAX, u128::MAX - 1, 7));
+    }
+
+    #[test]
+    fn fixed_points() {
+        let squares = PRODUCT_SQUARES;
+        let test = StressTest::new(Neighborhood::Moore(1), Aggregate::ProductMod(1000));
+        assert_eq!(Err(StressError::Exhausted { squares }), find_first_gt(test, 5));
+        let test = StressTest::new(Neighborhood::Moore(1), Aggregate::ProductMod(10))
+            .with_seed(5);
+        assert_eq!(Err(StressError::Exhausted { squares }), find_first_gt(test, 5));
+        let test = StressTest::new(Neighborhood::Moore(1), Aggregate::ProductMod(10))
+            .with_seed(5)
+            .with_limit(30);
+        assert_eq!(Err(StressError::Exhausted { squares: 30 }), find_first_gt(test, 5));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Err(StressError::Overflow { square: 1562 }),
+                   find_first_gt(StressTest::puzzle(), u128::MAX));
+        assert_eq!(Err(StressError::Exhausted { squares: 10 }),
+                   find_first_gt(StressTest::puzzle().with_limit(10), 100));
+        let test = StressTest::new(Neighborhood::Moore(1), Aggregate::ProductMod(7));
+        assert_eq!(Err(StressError::Unreachable { key: 6, bound: 7 }),
+                   find_first_gt(test, 6));
+    }
+
+    #[test]
+    fn zero_modulus() {
+        let test = StressTest::new(Neighborhood::Moore(1), Aggregate::ProductMod(0));
+        assert_eq!(Err(StressError::ZeroModulus), find_first_gt(test, 5));
+        let test = StressTest::new(Neighborhood::Moore(1), Aggregate::ProductMod(0));
+        let values: Vec<_> = test.collect();
+        assert_eq!(values, vec![Ok(1), Err(StressError::ZeroModulus)]);
+    }
+
+    #[test]
+    fn stationary() {
+        let test = StressTest::puzzle().with_seed(0);
+        assert_eq!(Err(StressError::Unreachable { key: 0, bound: 1 }),
+                   find_first_gt(test, 0));
+        let test = StressTest::new(Neighborhood::Moore(0), Aggregate::Sum);
+        assert_eq!(Err(StressError::Unreachable { key: 1, bound: 2 }),
+                   find_first_gt(test, 1));
+        let test = StressTest::new(Neighborhood::VonNeumann(0), Aggregate::Sum);
+        assert_eq!(Ok(1), find_first_gt(test, 0));
+        let test = StressTest::new(Neighborhood::VonNeumann(0), Aggregate::ProductMod(10))
+            .with_seed(0);
+        assert_eq!(Err(StressError::Unreachable { key: 3, bound: 2 }),
+                   find_first_gt(test, 3));
+    }
+
+    #[test]
+    fn render_ascii() {
+        assert_eq!(render::ascii(1, |i| i.to_string(), None),
+                   " 5  4  3\n 6  1  2\n 7  8  9\n");
+        assert_eq!(render::ascii(2, |i| i.to_string(), Some(12)),
+                   concat!(" 17  16  15  14  13\n",
+                           " 18   5 [ 4][ 3][12]\n",
+                           " 19   6 [ 1]  2  11\n",
+                           " 20   7   8   9  10\n",
+                           " 21  22  23  24  25\n"));
+
+        let values = render::stress_values(StressTest::puzzle(), 1);
+        assert_eq!(render::ascii(1, |i| values[i as usize - 1].to_string(), Some(7)),
+                   "  5   4   2\n 10 [ 1]  1\n[11][23] 25\n");
+    }
+
+    #[test]
+    fn render_ppm() {
+        let img = render::ppm(1, 2, |i| i as u8, Some(2));
+        let header = b"P6\n6 6\n255\n";
+        assert_eq!(&img[..header.len()], header);
+        let pixels = &img[header.len()..];
+        assert_eq!(pixels.len(), 6 * 6 * 3);
+        // top-left pixel is square 5, centre row holds the path 2 -> 1
+        assert_eq!(&pixels[..3], &[5, 5, 5]);
+        let centre = &pixels[2 * 6 * 3..3 * 6 * 3];
+        assert_eq!(&centre[..6], &[6, 6, 6, 6, 6, 6]);
+        assert_eq!(&centre[6..], &[255, 0, 0].repeat(4)[..]);
+    }
+
+    #[test]
+    fn render_args() {
+        let args = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(run_render("12", &args(&["ascii", "1"])).is_ok());
+        assert!(run_render("12", &args(&["stress"])).is_ok());
+        assert!(run_render("twelve", &args(&["ascii"])).is_err());
+        assert!(run_render("12", &args(&["svg"])).is_err());
+        assert!(run_render("12", &args(&["ascii", "-1"])).is_err());
+        assert!(run_render("12", &args(&["ascii", "3000000000"])).is_err());
+        assert!(run_render("12", &args(&["ppm", "2", "0"])).is_err());
+    }
+
     #[test]
     fn real_input() {
         let input = include_str!("../inputs/day03.txt");
@@ -1,5 +1,11 @@
-use std::collections::HashSet;
-use std::hash::Hash;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+use unicode_segmentation::UnicodeSegmentation;
+use rng::splitmix64;
 
 fn parse_input(s: &str) -> Vec<Vec<&str>> {
     s.lines()
@@ -7,26 +13,221 @@ fn parse_input(s: &str) -> Vec<Vec<&str>> {
         .collect()
 }
 
-fn all_unique<I: Iterator>(mut xs: I) -> bool where
-    I::Item: Eq + Hash
-{
-    let mut set = HashSet::new();
-    xs.all(|x| set.insert(x))
+// A word in canonical form: NFC, and with case folded (approximated by
+// uppercasing then lowercasing) if requested. Folding is applied to the
+// whole word before it is split into grapheme clusters, since it can
+// change the number of clusters: "ß" folds to "ss". Words already in
+// canonical form are borrowed rather than copied.
+fn canonical(w: &str, fold_case: bool) -> Cow<'_, str> {
+    if fold_case && !w.chars().all(folds_to_itself) {
+        Cow::Owned(w.chars()
+            .flat_map(char::to_uppercase)
+            .flat_map(char::to_lowercase)
+            .nfc()
+            .collect())
+    } else if is_nfc(w) {
+        Cow::Borrowed(w)
+    } else {
+        Cow::Owned(w.nfc().collect())
+    }
+}
+
+fn folds_to_itself(c: char) -> bool {
+    if c.is_ascii() {
+        return !c.is_ascii_uppercase();
+    }
+    let mut folded = c.to_uppercase().flat_map(char::to_lowercase);
+    folded.next() == Some(c) && folded.next().is_none()
+}
+
+/// Order-independent signature of the multiset of grapheme clusters in a
+/// word. Words that are anagrams always share a signature; different
+/// multisets share one only on a 128-bit hash collision.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct AnagramKey {
+    graphemes: usize,
+    sum: u64,
+    mixed: u64,
+}
+
+pub fn anagram_key(w: &str, fold_case: bool) -> AnagramKey {
+    let mut key = AnagramKey { graphemes: 0, sum: 0, mixed: 0 };
+    for g in canonical(w, fold_case).graphemes(true) {
+        let mut hasher = DefaultHasher::new();
+        g.chars().for_each(|c| hasher.write_u32(c as u32));
+        let h = hasher.finish();
+        key.graphemes += 1;
+        key.sum = key.sum.wrapping_add(h);
+        // mixed, so the two sums are independent
+        key.mixed = key.mixed.wrapping_add(splitmix64(h));
+    }
+    key
 }
 
-fn sort_word(w: &str) -> Vec<char> {
-    let mut v: Vec<_> = w.chars().collect();
+// Sorted canonical grapheme clusters: the exact anagram key. This one
+// allocates, so it is only used to confirm signature matches.
+fn sort_graphemes(w: &str, fold_case: bool) -> Vec<String> {
+    let mut v: Vec<String> = canonical(w, fold_case).graphemes(true)
+        .map(String::from)
+        .collect();
     v.sort();
     v
 }
 
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..b.len()+1).collect();
+    let mut curr = vec![0; b.len()+1];
+    for (i, ca) in a.chars().enumerate() {
+        curr[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let subst = prev[j] + if ca == cb { 0 } else { 1 };
+            curr[j+1] = subst.min(prev[j+1] + 1).min(curr[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[b.len()]
+}
+
+// Words whose keys collide, in order of first appearance.
+fn colliding<'a, K, F>(words: &[&'a str], key: F) -> Vec<&'a str> where
+    K: Eq + Hash,
+    F: Fn(&str) -> K
+{
+    let mut groups: HashMap<K, Vec<usize>> = HashMap::new();
+    for (i, w) in words.iter().enumerate() {
+        groups.entry(key(w)).or_default().push(i);
+    }
+    let mut hits: Vec<usize> = groups.into_iter()
+        .filter(|(_, ix)| ix.len() > 1)
+        .flat_map(|(_, ix)| ix)
+        .collect();
+    hits.sort();
+    hits.into_iter().map(|i| words[i]).collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    NoDuplicates,
+    /// No two words may be rearrangements of each other's grapheme
+    /// clusters, optionally ignoring case.
+    NoAnagrams { fold_case: bool },
+    /// No two words may be within this edit distance of each other.
+    NoNearWords(usize),
+    WordCount { min: usize, max: usize },
+    /// Every character must come from this set.
+    Alphabet(String),
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Rule::NoDuplicates => write!(f, "no duplicate words"),
+            Rule::NoAnagrams { fold_case: false } => write!(f, "no anagrams"),
+            Rule::NoAnagrams { fold_case: true } => write!(f, "no anagrams ignoring case"),
+            Rule::NoNearWords(k) => write!(f, "no words within edit distance {}", k),
+            Rule::WordCount { min, max } => write!(f, "between {} and {} words", min, max),
+            Rule::Alphabet(ref cs) => write!(f, "only characters from {:?}", cs),
+        }
+    }
+}
+
+impl Rule {
+    // The words that break this rule, if any.
+    fn offenders<'a>(&self, words: &[&'a str]) -> Vec<&'a str> {
+        match *self {
+            Rule::NoDuplicates => colliding(words, |w| w.to_string()),
+            Rule::NoAnagrams { fold_case } => {
+                let candidates = colliding(words, |w| anagram_key(w, fold_case));
+                colliding(&candidates, |w| sort_graphemes(w, fold_case))
+            },
+            Rule::NoNearWords(k) => {
+                let mut near = vec![false; words.len()];
+                for i in 0..words.len() {
+                    for j in i+1..words.len() {
+                        if levenshtein(words[i], words[j]) <= k {
+                            near[i] = true;
+                            near[j] = true;
+                        }
+                    }
+                }
+                words.iter().zip(near)
+                    .filter(|&(_, n)| n)
+                    .map(|(&w, _)| w)
+                    .collect()
+            },
+            Rule::WordCount { min, max } =>
+                if words.len() < min || words.len() > max {
+                    words.to_vec()
+                } else {
+                    vec![]
+                },
+            Rule::Alphabet(ref cs) =>
+                words.iter()
+                    .filter(|w| w.chars().any(|c| !cs.contains(c)))
+                    .cloned()
+                    .collect(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Violation<'a> {
+    pub rule: Rule,
+    pub words: Vec<&'a str>,
+}
+
+impl<'a> fmt::Display for Violation<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (violated by: {})", self.rule, self.words.join(" "))
+    }
+}
+
+/// A set of rules every word list in a passphrase must satisfy.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PassphrasePolicy {
+    rules: Vec<Rule>,
+}
+
+impl PassphrasePolicy {
+    pub fn new() -> PassphrasePolicy {
+        PassphrasePolicy { rules: Vec::new() }
+    }
+
+    pub fn with(mut self, rule: Rule) -> PassphrasePolicy {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Every rule the passphrase breaks, in the order the rules were added.
+    pub fn check<'a>(&self, words: &[&'a str]) -> Result<(), Vec<Violation<'a>>> {
+        let violations: Vec<Violation> = self.rules.iter()
+            .filter_map(|rule| {
+                let words = rule.offenders(words);
+                if words.is_empty() {
+                    None
+                } else {
+                    Some(Violation { rule: rule.clone(), words })
+                }
+            })
+            .collect();
+        if violations.is_empty() { Ok(()) } else { Err(violations) }
+    }
+
+    pub fn is_valid(&self, words: &[&str]) -> bool {
+        self.check(words).is_ok()
+    }
+}
+
 fn solve(input: &str) -> (usize, usize) {
     let ps = parse_input(input);
+    let weak = PassphrasePolicy::new().with(Rule::NoDuplicates);
+    let strong = PassphrasePolicy::new().with(Rule::NoAnagrams { fold_case: false });
     let part1 = ps.iter()
-        .filter(|p| all_unique(p.iter()))
+        .filter(|p| weak.is_valid(p))
         .count();
     let part2 = ps.iter()
-        .filter(|p| all_unique(p.iter().map(|w| sort_word(w))))
+        .filter(|p| strong.is_valid(p))
         .count();
     (part1, part2)
 }
@@ -70,6 +271,116 @@ mod tests {
                              ").1);
     }
 
+    #[test]
+    fn edit_distance() {
+        assert_eq!(0, levenshtein("abc", "abc"));
+        assert_eq!(1, levenshtein("abc", "abd"));
+        assert_eq!(1, levenshtein("abc", "ab"));
+        assert_eq!(3, levenshtein("kitten", "sitting"));
+        assert_eq!(4, levenshtein("", "abcd"));
+    }
+
+    #[test]
+    fn violations() {
+        let policy = PassphrasePolicy::new()
+            .with(Rule::NoDuplicates)
+            .with(Rule::NoAnagrams { fold_case: false })
+            .with(Rule::NoNearWords(1))
+            .with(Rule::WordCount { min: 2, max: 4 })
+            .with(Rule::Alphabet("abcdefghijklmnopqrstuvwxyz".to_string()));
+        assert_eq!(Ok(()), policy.check(&["abcde", "fghij"]));
+        assert_eq!(Err(vec![
+                Violation { rule: Rule::NoAnagrams { fold_case: false }, words: vec!["abcde", "ecdab"] },
+            ]),
+            policy.check(&["abcde", "xyz", "ecdab"]));
+        assert_eq!(Err(vec![
+                Violation { rule: Rule::NoDuplicates, words: vec!["aa", "aa"] },
+                Violation { rule: Rule::NoAnagrams { fold_case: false }, words: vec!["aa", "aa"] },
+                Violation { rule: Rule::NoNearWords(1), words: vec!["aa", "aa", "aaa"] },
+            ]),
+            policy.check(&["aa", "bb", "aa", "aaa"]));
+        assert_eq!(Err(vec![
+                Violation { rule: Rule::WordCount { min: 2, max: 4 }, words: vec!["Hi!"] },
+                Violation {
+                    rule: Rule::Alphabet("abcdefghijklmnopqrstuvwxyz".to_string()),
+                    words: vec!["Hi!"],
+                },
+            ]),
+            policy.check(&["Hi!"]));
+    }
+
+    #[test]
+    fn violation_display() {
+        let policy = PassphrasePolicy::new().with(Rule::NoNearWords(2));
+        let v = policy.check(&["cat", "dog", "cart"]).unwrap_err();
+        assert_eq!("no words within edit distance 2 (violated by: cat cart)",
+                   v[0].to_string());
+    }
+
+    #[test]
+    fn anagram_keys() {
+        assert_eq!(anagram_key("listen", false), anagram_key("silent", false));
+        assert!(anagram_key("listen", false) != anagram_key("Silent", false));
+        assert_eq!(anagram_key("listen", true), anagram_key("Silent", true));
+        assert!(anagram_key("aab", false) != anagram_key("abb", false));
+        // precomposed and decomposed e-acute are the same cluster
+        assert_eq!(anagram_key("caf\u{e9}", false), anagram_key("\u{e9}fac", false));
+        assert_eq!(anagram_key("caf\u{e9}", false), anagram_key("e\u{301}fac", false));
+        // a combining mark stays attached to its base letter
+        assert!(anagram_key("e\u{301}a", false) != anagram_key("a\u{301}e", false));
+        assert_eq!(anagram_key("\u{3a3}\u{39f}\u{3a3}", true),
+                   anagram_key("\u{3c3}\u{3bf}\u{3c2}", true));
+    }
+
+    const LOCALIZED: &str = "\
+caf\u{e9} face\u{301} \u{e9}fac
+Stra\u{df}e strasse STRASSE
+\u{3bb}\u{3cc}\u{3b3}\u{3bf}\u{3c2} \u{3b3}\u{3bb}\u{3cc}\u{3c3}\u{3bf}
+\u{928}\u{92e}\u{938}\u{94d}\u{924}\u{947} \u{924}\u{947}\u{928}\u{92e}\u{938}\u{94d}
+\u{1f468}\u{200d}\u{1f469} \u{1f469}\u{200d}\u{1f468} \u{1f468} \u{1f469}
+Ab\u{e7}d \u{c7}bad
+";
+
+    #[test]
+    fn localized_passphrases() {
+        let ps = parse_input(LOCALIZED);
+        let strict = PassphrasePolicy::new().with(Rule::NoAnagrams { fold_case: false });
+        let folded = PassphrasePolicy::new().with(Rule::NoAnagrams { fold_case: true });
+        let results: Vec<(bool, bool)> = ps.iter()
+            .map(|p| (strict.is_valid(p), folded.is_valid(p)))
+            .collect();
+        assert_eq!(results, vec![
+            (false, false), // café, face\u{301} and éfac in either form
+            (true, false),  // strasse/STRASSE differ only by case
+            (true, false),  // final sigma folds to sigma
+            (true, true),   // नमस्ते clusters differ from तेनमस्
+            (true, true),   // ZWJ sequences are single clusters
+            (true, false),  // Abçd/Çbad
+        ]);
+        assert_eq!(Err(vec![Violation {
+                rule: Rule::NoAnagrams { fold_case: false },
+                words: vec!["caf\u{e9}", "face\u{301}", "\u{e9}fac"],
+            }]),
+            strict.check(&ps[0]));
+    }
+
+    #[test]
+    fn full_case_folding() {
+        assert_eq!(anagram_key("Stra\u{df}e", true), anagram_key("strasse", true));
+        assert_eq!(anagram_key("Stra\u{df}e", true), anagram_key("sartess", true));
+        assert!(anagram_key("Stra\u{df}e", false) != anagram_key("strasse", false));
+        let folded = PassphrasePolicy::new().with(Rule::NoAnagrams { fold_case: true });
+        assert!(!folded.is_valid(&["Stra\u{df}e", "strasse"]));
+        assert!(!folded.is_valid(&["Stra\u{df}e", "SSTRASE"]));
+        assert!(folded.is_valid(&["Stra\u{df}e", "strase"]));
+
+        // words already folded and in NFC are not copied
+        assert!(matches!(canonical("strasse", true), Cow::Borrowed(_)));
+        assert!(matches!(canonical("caf\u{e9}", true), Cow::Borrowed(_)));
+        assert_eq!("cafe\u{301}".nfc().collect::<String>(), canonical("cafe\u{301}", true));
+        assert_eq!("strasse", canonical("Stra\u{df}e", true));
+    }
+
     #[test]
     fn real_input() {
         let input = include_str!("../inputs/day04.txt");
@@ -1,3 +1,11 @@
+use std::convert::Infallible;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+use rng::splitmix64;
+
 fn parse_input(s: &str) -> Vec<i32> {
     s.lines()
         .map(|m| m.parse().unwrap())
@@ -18,10 +26,378 @@ fn sim<F>(mut jumps: Vec<i32>, jump_fn: F) -> u32 where
     steps
 }
 
-fn solve(input: &str) -> (u32,u32) {
+// Under the part 2 rule an offset of 2 or 3 flips to the other one on every
+// visit, so once every cell in a block is 2 or 3 the block is "settled": it
+// only ever moves the pc forward and its state fits in one bit per cell
+// (set for 3). Settled blocks are stored as bit patterns instead.
+const BLOCK: usize = 64;
+
+// A pass through settled cells is scanned a window of cells at a time.
+// Which cells get visited depends only on the bits before the pass and on
+// how many cells are skipped before the first visit in the window (0, 1
+// or 2, carried over from the previous window).
+const WINDOW: usize = 16;
+const WINDOW_MASK: u64 = (1 << WINDOW) - 1;
+const ENTRY_BITS: usize = WINDOW + 2;
+const ENTRY_MASK: u64 = (1 << ENTRY_BITS) - 1;
+
+// For every window pattern, the outcome for each of the three carried skip
+// counts, packed side by side: a mask of the visited cells, then the skip
+// count carried into the next window. Packing all three means the table
+// lookup does not wait on the previous window's result.
+fn window_table() -> &'static [u64] {
+    static TABLE: OnceLock<Vec<u64>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        (0..1usize << WINDOW)
+            .map(|pattern| {
+                (0..3).fold(0, |entry, skip| {
+                    let mut k = skip;
+                    let mut visited = 0;
+                    while k < WINDOW {
+                        visited |= 1 << k;
+                        k += 2 + (pattern >> k & 1);
+                    }
+                    let out = visited | ((k - WINDOW) as u64) << WINDOW;
+                    entry | out << (ENTRY_BITS * skip)
+                })
+            })
+            .collect()
+    })
+}
+
+fn is_settled(jump: i32) -> bool {
+    jump == 2 || jump == 3
+}
+
+/// Same result as `sim` with the part 2 rule, but passes through runs of
+/// settled blocks a window at a time instead of one step per jump.
+fn skip_sim(mut jumps: Vec<i32>) -> u64 {
+    let table = window_table();
+    let len = jumps.len();
+    let num_blocks = len.div_ceil(BLOCK);
+
+    // Cells in each block that are not yet 2 or 3. The padding past the
+    // end of a partial last block counts, so that block is never settled.
+    let mut unsettled: Vec<usize> = jumps.chunks(BLOCK)
+        .map(|ch| ch.iter().filter(|&&j| !is_settled(j)).count() + BLOCK - ch.len())
+        .collect();
+    // Authoritative state of settled blocks; `jumps` goes stale there.
+    let mut patterns = vec![0u64; num_blocks];
+    let pattern_of = |block: &[i32]| block.iter().enumerate()
+        .fold(0, |p, (i, &j)| p | ((j == 3) as u64) << i);
+    for (b, ch) in jumps.chunks(BLOCK).enumerate() {
+        if unsettled[b] == 0 {
+            patterns[b] = pattern_of(ch);
+        }
+    }
+
+    let mut pc = 0i64;
+    let mut steps = 0;
+    while pc >= 0 && (pc as usize) < len {
+        let i = pc as usize;
+        let mut b = i / BLOCK;
+        if unsettled[b] == 0 {
+            // Step to the end of the current window one cell at a time...
+            let mut p = patterns[b];
+            let mut visited = 0;
+            let mut k = i % BLOCK;
+            let boundary = (k / WINDOW + 1) * WINDOW;
+            while k < boundary {
+                visited |= 1 << k;
+                k += 2 + (p >> k & 1) as usize;
+            }
+            let mut skip = k - boundary;
+            let mut w = boundary / WINDOW;
+
+            // ...then a window at a time until the pc reaches a block
+            // that is not settled.
+            loop {
+                while w < BLOCK / WINDOW {
+                    let e = table[(p >> (WINDOW * w) & WINDOW_MASK) as usize]
+                        >> (ENTRY_BITS * skip) & ENTRY_MASK;
+                    visited |= (e & WINDOW_MASK) << (WINDOW * w);
+                    skip = (e >> WINDOW) as usize;
+                    w += 1;
+                }
+                patterns[b] = p ^ visited;
+                steps += visited.count_ones() as u64;
+                b += 1;
+                if b >= num_blocks || unsettled[b] != 0 {
+                    break;
+                }
+                p = patterns[b];
+                visited = 0;
+                w = 0;
+            }
+            pc = (b * BLOCK + skip) as i64;
+        } else {
+            let jump = jumps[i];
+            let next = if jump < 3 { jump + 1 } else { jump - 1 };
+            jumps[i] = next;
+            if !is_settled(jump) && is_settled(next) {
+                unsettled[b] -= 1;
+                if unsettled[b] == 0 {
+                    patterns[b] = pattern_of(&jumps[b*BLOCK..(b+1)*BLOCK]);
+                }
+            }
+            pc += jump as i64;
+            steps += 1;
+        }
+    }
+    steps
+}
+
+/// How a simulation ended.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Outcome {
+    /// The pc left the jump list after this many steps.
+    Escaped { steps: u64 },
+    /// The step budget ran out first.
+    BudgetExhausted { steps: u64 },
+    /// The state after `start` steps recurs every `period` steps, so the
+    /// program can never escape.
+    Cycle { start: u64, period: u64 },
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Outcome::Escaped { steps } =>
+                write!(f, "escaped after {} steps", steps),
+            Outcome::BudgetExhausted { steps } =>
+                write!(f, "still running after {} steps", steps),
+            Outcome::Cycle { start, period } =>
+                write!(f, "loops forever from step {} with period {}", start, period),
+        }
+    }
+}
+
+fn mix(i: usize, jump: i32) -> u64 {
+    splitmix64((i as u64) << 32 | jump as u32 as u64)
+}
+
+// Full machine state. The hash is kept up to date as offsets change so
+// that unequal states are almost always told apart without comparing the
+// whole jump list. Field order matters: derived eq checks them in turn.
+#[derive(Clone, PartialEq)]
+struct State {
+    pc: i64,
+    hash: u64,
+    jumps: Vec<i32>,
+}
+
+impl State {
+    fn new(jumps: Vec<i32>) -> State {
+        let hash = jumps.iter().enumerate()
+            .fold(0u64, |h, (i, &j)| h.wrapping_add(mix(i, j)));
+        State { pc: 0, hash, jumps }
+    }
+
+    // Take one jump, returning the (pc, offset) it used, or None if the pc
+    // is already outside the list. The state is unchanged if the jump
+    // function fails.
+    fn step<E, J>(&mut self, jump_fn: &J) -> Result<Option<(i64, i32)>, E> where
+        J: Fn(i32) -> Result<i32, E>
+    {
+        if self.pc < 0 || self.pc >= self.jumps.len() as i64 {
+            return Ok(None);
+        }
+        let i = self.pc as usize;
+        let jump = self.jumps[i];
+        let next = jump_fn(jump)?;
+        self.jumps[i] = next;
+        self.hash = self.hash.wrapping_sub(mix(i, jump)).wrapping_add(mix(i, next));
+        self.pc += jump as i64;
+        Ok(Some((i as i64, jump)))
+    }
+}
+
+/// Jump-maze simulator with an optional step budget and cycle detection.
+pub struct Simulator<F> {
+    jump_fn: F,
+    budget: Option<u64>,
+    detect_cycles: bool,
+}
+
+impl<F> Simulator<F> {
+    pub fn new(jump_fn: F) -> Simulator<F> {
+        Simulator { jump_fn, budget: None, detect_cycles: false }
+    }
+
+    /// Give up after this many steps.
+    pub fn budget(mut self, steps: u64) -> Simulator<F> {
+        self.budget = Some(steps);
+        self
+    }
+
+    /// Check for repeated states using Brent's algorithm. This keeps one
+    /// extra copy of the jump list, refreshed at power-of-two step counts.
+    pub fn detect_cycles(mut self, on: bool) -> Simulator<F> {
+        self.detect_cycles = on;
+        self
+    }
+
+    fn execute<E, J, T>(&self, jump_fn: J, jumps: Vec<i32>, mut trace: T) -> Result<Outcome, E> where
+        J: Fn(i32) -> Result<i32, E>,
+        T: FnMut(i64, i32) -> Result<(), E>
+    {
+        let mut hare = State::new(jumps);
+        let initial = if self.detect_cycles { Some(hare.clone()) } else { None };
+        let mut tortoise = initial.clone();
+        let mut power = 1;
+        let mut lambda = 0;
+        let mut steps = 0;
+        loop {
+            if self.budget.is_some_and(|b| steps >= b) {
+                return Ok(Outcome::BudgetExhausted { steps });
+            }
+            match hare.step(&jump_fn)? {
+                Some((pc, jump)) => trace(pc, jump)?,
+                None => return Ok(Outcome::Escaped { steps }),
+            }
+            steps += 1;
+
+            if let Some(ref mut t) = tortoise {
+                lambda += 1;
+                if *t == hare {
+                    let start = Self::cycle_start(&jump_fn, initial.unwrap(), lambda)?;
+                    return Ok(Outcome::Cycle { start, period: lambda });
+                }
+                if lambda == power {
+                    t.clone_from(&hare);
+                    power *= 2;
+                    lambda = 0;
+                }
+            }
+        }
+    }
+
+    // Find the first state that lies on the cycle, given its period.
+    fn cycle_start<E, J>(jump_fn: &J, initial: State, period: u64) -> Result<u64, E> where
+        J: Fn(i32) -> Result<i32, E>
+    {
+        let mut a = initial.clone();
+        let mut b = initial;
+        for _ in 0..period {
+            b.step(jump_fn)?;
+        }
+        let mut start = 0;
+        while a != b {
+            a.step(jump_fn)?;
+            b.step(jump_fn)?;
+            start += 1;
+        }
+        Ok(start)
+    }
+}
+
+impl<F: Fn(i32) -> i32> Simulator<F> {
+    pub fn run(&self, jumps: Vec<i32>) -> Outcome {
+        let jump_fn = |j| Ok((self.jump_fn)(j));
+        let ok: Result<Outcome, Infallible> = self.execute(jump_fn, jumps, |_, _| Ok(()));
+        ok.unwrap_or_else(|e| match e {})
+    }
+
+    /// Run, writing a "pc offset" line to `out` for every step taken.
+    pub fn run_traced<W: Write>(&self, jumps: Vec<i32>, out: &mut W) -> io::Result<Outcome> {
+        let jump_fn = |j| Ok((self.jump_fn)(j));
+        self.execute(jump_fn, jumps, |pc, jump| writeln!(out, "{} {}", pc, jump))
+    }
+
+    pub fn trace_to_file<P: AsRef<Path>>(&self, jumps: Vec<i32>, path: P) -> io::Result<Outcome> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let outcome = self.run_traced(jumps, &mut out)?;
+        out.flush()?;
+        Ok(outcome)
+    }
+}
+
+impl<E, F: Fn(i32) -> Result<i32, E>> Simulator<F> {
+    /// Run with a jump function that can fail, stopping at the first error.
+    pub fn try_run(&self, jumps: Vec<i32>) -> Result<Outcome, E> {
+        self.execute(&self.jump_fn, jumps, |_, _| Ok(()))
+    }
+}
+
+/// Offset-mutation rules written as expressions in the offset `j`, e.g.
+/// `if j >= 3 then j-1 else j+1`. See `expr` for the expression syntax;
+/// `j` is the only variable and `if` expressions are allowed.
+pub mod rule {
+    use std::fmt;
+    use std::str::FromStr;
+    use expr::{Expr, Parser};
+    pub use expr::{EvalError, ParseError};
+
+    /// A parsed offset-mutation rule.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Rule {
+        source: String,
+        expr: Expr,
+    }
+
+    impl Rule {
+        /// The new offset for a cell whose offset was `j`.
+        pub fn eval(&self, j: i32) -> Result<i32, EvalError> {
+            let x = self.expr.eval(&|_| j as i64)?;
+            if x < i32::MIN as i64 || x > i32::MAX as i64 {
+                Err(EvalError::Overflow)
+            } else {
+                Ok(x as i32)
+            }
+        }
+
+        /// Like `eval`, but panics if the rule cannot be evaluated. Pass
+        /// `eval` to `Simulator::try_run` to get the error instead.
+        pub fn apply(&self, j: i32) -> i32 {
+            self.eval(j).unwrap_or_else(|e| {
+                panic!("rule '{}' failed for j = {}: {}", self.source, j, e)
+            })
+        }
+    }
+
+    impl fmt::Display for Rule {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{}", self.source)
+        }
+    }
+
+    impl FromStr for Rule {
+        type Err = ParseError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let mut parser = Parser::new(s)?.conditionals(true).vars(&["j"]);
+            let expr = parser.expr()?;
+            parser.finish("expression")?;
+            Ok(Rule { source: s.trim().to_string(), expr })
+        }
+    }
+}
+
+// Each spec is either a rule or `@file` naming a file with one rule per
+// line (blank lines and lines starting with # are skipped).
+fn load_rules(specs: &[String]) -> Result<Vec<rule::Rule>, String> {
+    let mut sources = Vec::new();
+    for spec in specs {
+        if let Some(path) = spec.strip_prefix('@') {
+            let text = fs::read_to_string(path)
+                .map_err(|e| format!("{}: {}", path, e))?;
+            sources.extend(text.lines()
+                .map(|l| l.trim())
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(String::from));
+        } else {
+            sources.push(spec.clone());
+        }
+    }
+    sources.iter()
+        .map(|src| src.parse().map_err(|e| format!("bad rule '{}' {}", src, e)))
+        .collect()
+}
+
+fn solve(input: &str) -> (u32,u64) {
     let jumps = parse_input(input);
     let part1 = sim(jumps.clone(), |j| j+1);
-    let part2 = sim(jumps.clone(), |j| if j < 3 { j+1 } else { j-1 });
+    let part2 = skip_sim(jumps);
     (part1, part2)
 }
 
@@ -31,9 +407,25 @@ pub fn run(input: &str) {
     println!("the solution to part 2 is {:?}", part2);
 }
 
+/// Run the jump list under each of the given rules instead of the puzzle's.
+pub fn run_rules(input: &str, specs: &[String]) -> Result<(), String> {
+    const BUDGET: u64 = 1_000_000_000;
+    let jumps = parse_input(input);
+    for rule in load_rules(specs)? {
+        let outcome = Simulator::new(|j| rule.eval(j))
+            .budget(BUDGET)
+            .detect_cycles(true)
+            .try_run(jumps.clone())
+            .map_err(|e| format!("rule '{}' failed: {}", rule, e))?;
+        println!("{}: {}", rule, outcome);
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rng::XorShift32;
 
     #[test]
     fn parsing() {
@@ -48,6 +440,149 @@ mod tests {
         assert_eq!(10, part2);
     }
 
+    #[test]
+    fn skipping() {
+        assert_eq!(10, skip_sim(vec![0, 3, 0, 1, -3]));
+
+        // fixed seed, so the lists are the same on every run
+        let mut rng = XorShift32::new(0x2017_0005);
+        let mut rand = || rng.next_u32();
+        for _ in 0..200 {
+            let len = (rand() % 1000) as usize + 1;
+            let jumps: Vec<i32> = (0..len)
+                .map(|_| (rand() % 13) as i32 - 6)
+                .collect();
+            let expected = sim(jumps.clone(), |j| if j < 3 { j+1 } else { j-1 });
+            assert_eq!(expected as u64, skip_sim(jumps));
+        }
+        // shaped like puzzle inputs, mostly jumping back towards the start,
+        // so long settled runs build up
+        for _ in 0..5 {
+            let len = (rand() % 400) as usize + 200;
+            let jumps: Vec<i32> = (0..len)
+                .map(|i| 2 - (rand() as usize % (i + 1)) as i32)
+                .collect();
+            let expected = sim(jumps.clone(), |j| if j < 3 { j+1 } else { j-1 });
+            assert_eq!(expected as u64, skip_sim(jumps));
+        }
+    }
+
+    #[test]
+    fn simulator() {
+        let jumps = parse_input("0\n3\n0\n1\n-3\n");
+        let strange = |j| if j < 3 { j+1 } else { j-1 };
+        assert_eq!(Outcome::Escaped { steps: 5 },
+                   Simulator::new(|j| j+1).run(jumps.clone()));
+        assert_eq!(Outcome::Escaped { steps: 10 },
+                   Simulator::new(strange).detect_cycles(true).run(jumps.clone()));
+        assert_eq!(Outcome::BudgetExhausted { steps: 4 },
+                   Simulator::new(|j| j+1).budget(4).run(jumps.clone()));
+    }
+
+    #[test]
+    fn cycles() {
+        assert_eq!(Outcome::Cycle { start: 0, period: 1 },
+                   Simulator::new(|j| j).detect_cycles(true).run(vec![0]));
+        assert_eq!(Outcome::Cycle { start: 0, period: 2 },
+                   Simulator::new(|j| j).detect_cycles(true).run(vec![1, -1]));
+        // the first jump lands in a 2 -> 1 -> 2 loop that never changes
+        assert_eq!(Outcome::Cycle { start: 1, period: 2 },
+                   Simulator::new(|j| if j == 2 { -1 } else { j })
+                       .detect_cycles(true)
+                       .run(vec![2, 1, -1]));
+        assert_eq!(Outcome::BudgetExhausted { steps: 100 },
+                   Simulator::new(|j| j).budget(100).run(vec![1, -1]));
+    }
+
+    #[test]
+    fn trace() {
+        let mut out = Vec::new();
+        let outcome = Simulator::new(|j| j+1)
+            .run_traced(vec![0, 3, 0, 1, -3], &mut out)
+            .unwrap();
+        assert_eq!(Outcome::Escaped { steps: 5 }, outcome);
+        assert_eq!("0 0\n0 1\n1 3\n4 -3\n1 4\n", String::from_utf8(out).unwrap());
+
+        let path = std::env::temp_dir().join("advent2017-day05-trace.txt");
+        let outcome = Simulator::new(|j| j).detect_cycles(true)
+            .trace_to_file(vec![1, -1], &path)
+            .unwrap();
+        assert_eq!(Outcome::Cycle { start: 0, period: 2 }, outcome);
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!("0 1\n1 -1\n0 1\n", written);
+    }
+
+    #[test]
+    fn rules() {
+        let r: rule::Rule = "if j >= 3 then j-1 else j+1".parse().unwrap();
+        assert_eq!(Ok(1), r.eval(0));
+        assert_eq!(Ok(3), r.eval(2));
+        assert_eq!(Ok(2), r.eval(3));
+        assert_eq!(Ok(-4), r.eval(-5));
+
+        let eval = |src: &str, j| src.parse::<rule::Rule>().unwrap().eval(j);
+        assert_eq!(Ok(7), eval("1 + 2 * 3", 0));
+        assert_eq!(Ok(9), eval("(1 + 2) * 3", 0));
+        assert_eq!(Ok(-3), eval("-j - 1", 2));
+        assert_eq!(Ok(1), eval("j > 0 && j % 2 == 0 || !j", 4));
+        assert_eq!(Ok(0), eval("j > 0 && j % 2 == 0 || !j", 3));
+        assert_eq!(Ok(1), eval("!j", 0));
+        assert_eq!(Ok(5), eval("if j < 0 then 0 else if j < 10 then 5 else 10", 3));
+        assert_eq!(Ok(0), eval("j != 0 && 10 / j > 1", 0));
+        assert_eq!(Err(rule::EvalError::DivideByZero), eval("10 / j", 0));
+        assert_eq!(Err(rule::EvalError::Overflow), eval("j * 1000000", 100000));
+    }
+
+    #[test]
+    fn rule_errors() {
+        let err = |src: &str| src.parse::<rule::Rule>().unwrap_err().to_string();
+        assert_eq!("at column 1: unknown word 'k'", err("k + 1"));
+        assert_eq!("at column 3: use '==' for comparison", err("j = 1"));
+        assert_eq!("at column 10: expected 'then'", err("if j > 1 j else 0"));
+        assert_eq!("at column 4: expected a value", err("j +"));
+        assert_eq!("at column 5: expected ')'", err("(j+1"));
+        assert_eq!("at column 3: unexpected input after expression", err("j 1"));
+        assert_eq!("at column 3: unexpected '$'", err("j $ 1"));
+    }
+
+    #[test]
+    fn rules_in_simulator() {
+        let jumps = parse_input("0\n3\n0\n1\n-3\n");
+        let part2: rule::Rule = "if j >= 3 then j-1 else j+1".parse().unwrap();
+        assert_eq!(10, sim(jumps.clone(), |j| part2.apply(j)));
+
+        let specs = vec!["j+1".to_string(), "# not a rule".to_string()];
+        assert!(load_rules(&specs).is_err());
+
+        let path = std::env::temp_dir().join("advent2017-day05-rules.txt");
+        fs::write(&path, "# part 1\nj+1\n\nif j >= 3 then j-1 else j+1\n").unwrap();
+        let specs = vec!["j".to_string(), format!("@{}", path.display())];
+        let rules = load_rules(&specs);
+        fs::remove_file(&path).unwrap();
+        let rules: Vec<String> = rules.unwrap().iter().map(|r| r.to_string()).collect();
+        assert_eq!(rules, vec!["j", "j+1", "if j >= 3 then j-1 else j+1"]);
+    }
+
+    #[test]
+    fn failing_rules() {
+        let jumps = parse_input("0\n3\n0\n1\n-3\n");
+        let part2: rule::Rule = "if j >= 3 then j-1 else j+1".parse().unwrap();
+        assert_eq!(Ok(Outcome::Escaped { steps: 10 }),
+                   Simulator::new(|j| part2.eval(j)).try_run(jumps.clone()));
+
+        let div: rule::Rule = "10 / j".parse().unwrap();
+        assert_eq!(Err(rule::EvalError::DivideByZero),
+                   Simulator::new(|j| div.eval(j)).try_run(jumps.clone()));
+        let grow: rule::Rule = "j * 3000000000".parse().unwrap();
+        assert_eq!(Err(rule::EvalError::Overflow),
+                   Simulator::new(|j| grow.eval(j)).try_run(vec![1]));
+
+        let specs = vec!["j+1".to_string(), "10 / j".to_string()];
+        assert_eq!(Err("rule '10 / j' failed: division by zero".to_string()),
+                   run_rules("0\n3\n0\n1\n-3\n", &specs));
+    }
+
     #[test]
     fn real_input() {
         let input = include_str!("../inputs/day05.txt");
@@ -1,4 +1,8 @@
 use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::Instant;
+use rng::XorShift32;
 
 fn parse_input(s: &str) -> Vec<u32> {
     s.trim().split_whitespace()
@@ -13,35 +17,205 @@ fn find_most(banks: &[u32]) -> (usize, u32) {
     (i,*c)
 }
 
-fn reallocate(banks: &mut [u32]) {
-    let (mut i, mut c) = find_most(banks);
-    banks[i] = 0;
-    while c > 0 {
-        i = (i + 1) % banks.len();
-        banks[i] += 1;
-        c -= 1;
+// Hand out `blocks` over the `k` banks after `from`, wrapping around, as
+// if dealing one at a time starting with the next bank.
+fn deal(banks: &mut [u32], from: usize, blocks: u32, k: usize) {
+    let n = banks.len();
+    let (q, r) = (blocks / k as u32, (blocks % k as u32) as usize);
+    for j in 1..k+1 {
+        banks[(from + j) % n] += q + (j <= r) as u32;
     }
 }
 
-fn solve(input: &str) -> (u32, u32) {
-    let mut banks = parse_input(input);
+/// Decides which bank gets emptied and where its blocks go. A policy must
+/// be a pure function of the configuration, or loop detection is meaningless.
+pub trait ReallocPolicy {
+    /// Index of the bank to empty.
+    fn choose(&self, banks: &[u32]) -> usize;
+
+    /// How many of the following banks share its blocks. The default of
+    /// every bank includes the emptied one when the deal wraps around.
+    fn spread(&self, banks: &[u32]) -> usize {
+        banks.len()
+    }
+
+    fn reallocate(&self, banks: &mut [u32]) {
+        let i = self.choose(banks);
+        let blocks = banks[i];
+        banks[i] = 0;
+        let k = self.spread(banks).clamp(1, banks.len());
+        deal(banks, i, blocks, k);
+    }
+}
+
+/// The puzzle's policy: empty the fullest bank, lowest index first.
+pub struct MostBlocks;
+
+impl ReallocPolicy for MostBlocks {
+    fn choose(&self, banks: &[u32]) -> usize {
+        find_most(banks).0
+    }
+}
+
+/// Empty the emptiest bank, lowest index first.
+pub struct FewestBlocks;
+
+impl ReallocPolicy for FewestBlocks {
+    fn choose(&self, banks: &[u32]) -> usize {
+        banks.iter().enumerate()
+            .min_by_key(|&(_, c)| c)
+            .unwrap().0
+    }
+}
+
+/// Empty a fullest bank, breaking ties pseudo-randomly. The choice is a
+/// hash of the seed and the configuration, so it is repeatable.
+pub struct RandomTieBreak {
+    pub seed: u64,
+}
+
+impl ReallocPolicy for RandomTieBreak {
+    fn choose(&self, banks: &[u32]) -> usize {
+        let most = find_most(banks).1;
+        let ties: Vec<usize> = (0..banks.len()).filter(|&i| banks[i] == most).collect();
+        let mut h = DefaultHasher::new();
+        self.seed.hash(&mut h);
+        banks.hash(&mut h);
+        ties[(h.finish() % ties.len() as u64) as usize]
+    }
+}
+
+/// Empty the fullest bank, but share its blocks among only the next `k`
+/// banks.
+pub struct Neighbours {
+    pub k: usize,
+}
+
+impl ReallocPolicy for Neighbours {
+    fn choose(&self, banks: &[u32]) -> usize {
+        find_most(banks).0
+    }
+
+    fn spread(&self, _banks: &[u32]) -> usize {
+        self.k
+    }
+}
+
+/// How to find the reallocation loop.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Method {
+    /// Remember every configuration seen. Fast, but memory grows with
+    /// the number of steps before the repeat.
+    Hash,
+    /// Brent's algorithm: keeps only three configurations at a time, at
+    /// the cost of running the reallocation up to about three times as often.
+    Brent,
+}
+
+fn find_loop_hash<P: ReallocPolicy + ?Sized>(banks: &[u32], policy: &P) -> (u32, u32) {
+    let mut banks = banks.to_vec();
     let mut states = HashMap::new();
     let mut count = 0;
     loop {
         if let Some(k) = states.insert(banks.clone(), count) {
             return (count, count - k);
         }
-        reallocate(&mut banks);
+        policy.reallocate(&mut banks);
         count += 1;
     }
 }
 
+fn find_loop_brent<P: ReallocPolicy + ?Sized>(banks: &[u32], policy: &P) -> (u32, u32) {
+    // Find the loop size: the tortoise waits at power-of-two steps for
+    // the hare to come back around.
+    let mut tortoise = banks.to_vec();
+    let mut hare = banks.to_vec();
+    policy.reallocate(&mut hare);
+    let mut power = 1;
+    let mut loop_size = 1;
+    while tortoise != hare {
+        if power == loop_size {
+            tortoise.copy_from_slice(&hare);
+            power *= 2;
+            loop_size = 0;
+        }
+        policy.reallocate(&mut hare);
+        loop_size += 1;
+    }
+
+    // Find where the loop starts: run two copies a loop apart until
+    // they meet.
+    tortoise.copy_from_slice(banks);
+    hare.copy_from_slice(banks);
+    for _ in 0..loop_size {
+        policy.reallocate(&mut hare);
+    }
+    let mut start = 0;
+    while tortoise != hare {
+        policy.reallocate(&mut tortoise);
+        policy.reallocate(&mut hare);
+        start += 1;
+    }
+    (start + loop_size, loop_size)
+}
+
+/// Returns the number of reallocations before a configuration repeats,
+/// and the size of the loop.
+pub fn find_loop<P>(banks: &[u32], policy: &P, method: Method) -> (u32, u32) where
+    P: ReallocPolicy + ?Sized
+{
+    match method {
+        Method::Hash => find_loop_hash(banks, policy),
+        Method::Brent => find_loop_brent(banks, policy),
+    }
+}
+
+fn solve(input: &str) -> (u32, u32) {
+    let banks = parse_input(input);
+    find_loop(&banks, &MostBlocks, Method::Hash)
+}
+
+// A fixed pseudo-random configuration for timing runs.
+fn bench_banks(n: usize) -> Vec<u32> {
+    let mut rng = XorShift32::new(0x2017_0006);
+    (0..n).map(|_| rng.next_u32() % 21).collect()
+}
+
 pub fn run(input: &str) {
     let (part1, part2) = solve(input);
     println!("the first repeat is at {}", part1);
     println!("the loop size is {}", part2);
 }
 
+// Time both loop-finding methods on a generated configuration.
+fn run_bench() {
+    const REPS: u32 = 10;
+    let banks = bench_banks(64);
+    for &method in &[Method::Hash, Method::Brent] {
+        let now = Instant::now();
+        let mut result = (0, 0);
+        for _ in 0..REPS {
+            result = find_loop(&banks, &MostBlocks, method);
+        }
+        let e = now.elapsed();
+        println!("{:?}: {} runs on 64 banks (repeat at {}, loop size {}) in {}.{:03}s",
+                 method, REPS, result.0, result.1, e.as_secs(), e.subsec_millis());
+    }
+}
+
+/// Run in another mode instead of solving:
+///
+/// * `bench`: ignore the input and time each method on 64 generated banks
+pub fn run_mode(_input: &str, mode: &str) -> Result<(), String> {
+    match mode {
+        "bench" => {
+            run_bench();
+            Ok(())
+        },
+        _ => Err(format!("unknown mode '{}' (expected bench)", mode)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -59,6 +233,72 @@ mod tests {
         assert_eq!(4, loop_size);
     }
 
+    #[test]
+    fn brent() {
+        assert_eq!((5, 4), find_loop(&[0, 2, 7, 0], &MostBlocks, Method::Brent));
+        let policies: Vec<Box<dyn ReallocPolicy>> = vec![
+            Box::new(MostBlocks),
+            Box::new(FewestBlocks),
+            Box::new(RandomTieBreak { seed: 17 }),
+            Box::new(Neighbours { k: 3 }),
+        ];
+        for policy in &policies {
+            for n in 1..20 {
+                let banks = bench_banks(n);
+                assert_eq!(find_loop(&banks, policy.as_ref(), Method::Hash),
+                           find_loop(&banks, policy.as_ref(), Method::Brent));
+            }
+        }
+        let banks = bench_banks(64);
+        assert_eq!(find_loop(&banks, &MostBlocks, Method::Hash),
+                   find_loop(&banks, &MostBlocks, Method::Brent));
+    }
+
+    // The original one-block-at-a-time reallocation.
+    fn reallocate_slowly(banks: &mut [u32]) {
+        let (mut i, mut c) = find_most(banks);
+        banks[i] = 0;
+        while c > 0 {
+            i = (i + 1) % banks.len();
+            banks[i] += 1;
+            c -= 1;
+        }
+    }
+
+    #[test]
+    fn bulk_deal() {
+        for n in 1..20 {
+            let mut fast = bench_banks(n);
+            fast[0] = 1000 + n as u32;
+            let mut slow = fast.clone();
+            for _ in 0..50 {
+                MostBlocks.reallocate(&mut fast);
+                reallocate_slowly(&mut slow);
+                assert_eq!(fast, slow);
+            }
+        }
+    }
+
+    #[test]
+    fn policies() {
+        let mut banks = vec![3, 1, 4, 2];
+        FewestBlocks.reallocate(&mut banks);
+        assert_eq!(vec![3, 0, 5, 2], banks);
+
+        let mut banks = vec![0, 2, 7, 0, 0];
+        Neighbours { k: 2 }.reallocate(&mut banks);
+        assert_eq!(vec![0, 2, 0, 4, 3], banks);
+
+        // ties are broken the same way every time, but not always lowest first
+        let banks = vec![5, 1, 5, 5];
+        let picks: Vec<usize> = (0..20)
+            .map(|seed| RandomTieBreak { seed }.choose(&banks))
+            .collect();
+        assert!(picks.iter().all(|&i| banks[i] == 5));
+        assert!(picks.iter().any(|&i| i != 0));
+        assert_eq!(picks[3], RandomTieBreak { seed: 3 }.choose(&banks));
+    }
+
     #[test]
     fn real_input() {
         let input = include_str!("../inputs/day06.txt");
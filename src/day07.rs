@@ -1,27 +1,184 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 #[derive(Debug,PartialEq)]
 struct Program<'a> {
     weight: u32,
     total_weight: u32,
     subprogs: Vec<&'a str>,
+    line: usize,
 }
 
-fn parse_input(s: &str) -> HashMap<&str, Program> {
-    s.lines().map(|line| {
-        let mut s = line.split(" -> ");
-        let base = s.next().unwrap();
-        let mut base = base.split(" (");
-        let name = base.next().unwrap();
-        let weight = base.next().unwrap();
-        let weight = weight[..weight.len()-1].parse().unwrap();
-        let subprogs = if let Some(x_sup) = s.next() {
-            x_sup.split(", ").collect()
-        } else {
-            vec![]
-        };
-        (name, Program { weight, total_weight: 0, subprogs })
-        }).collect()
+fn parse_line(line: &str, line_no: usize) -> Option<(&str, Program<'_>)> {
+    let mut s = line.split(" -> ");
+    let base = s.next()?;
+    let mut base = base.split(" (");
+    let name = base.next()?;
+    let weight = base.next()?.strip_suffix(')')?.parse().ok()?;
+    let subprogs = if let Some(x_sup) = s.next() {
+        x_sup.split(", ").collect()
+    } else {
+        vec![]
+    };
+    Some((name, Program { weight, total_weight: 0, subprogs, line: line_no }))
+}
+
+fn parse_input(s: &str) -> HashMap<&str, Program<'_>> {
+    s.lines().enumerate()
+        .map(|(i, line)| parse_line(line, i + 1).unwrap())
+        .collect()
+}
+
+/// Something that stops the input from describing a single tower.
+#[derive(Debug, PartialEq)]
+pub enum Problem<'a> {
+    /// The input defines no programs at all.
+    Empty,
+    Malformed { line: usize },
+    Duplicate { name: &'a str, lines: Vec<usize> },
+    MissingChild { parent: &'a str, child: &'a str, line: usize },
+    /// The child and the lines of every program holding it.
+    MultipleParents { child: &'a str, lines: Vec<usize> },
+    /// Programs with children but no parent.
+    MultipleRoots { lines: Vec<usize> },
+    /// Every program has a parent, so there is nothing to stand on.
+    NoRoot,
+    /// Programs that hold each other up, listed in order around the loop.
+    Cycle { names: Vec<&'a str>, lines: Vec<usize> },
+    /// A program with no parent and no children beside the tower.
+    Orphan { name: &'a str, line: usize },
+}
+
+impl<'a> fmt::Display for Problem<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let list = |ls: &[usize]| ls.iter()
+            .map(|l| l.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        match *self {
+            Problem::Empty =>
+                write!(f, "no programs"),
+            Problem::Malformed { line } =>
+                write!(f, "line {}: cannot parse program", line),
+            Problem::Duplicate { name, ref lines } =>
+                write!(f, "lines {}: {} is defined more than once", list(lines), name),
+            Problem::MissingChild { parent, child, line } =>
+                write!(f, "line {}: {} holds undefined program {}", line, parent, child),
+            Problem::MultipleParents { child, ref lines } =>
+                write!(f, "lines {}: {} is held by more than one program", list(lines), child),
+            Problem::MultipleRoots { ref lines } =>
+                write!(f, "lines {}: more than one bottom program", list(lines)),
+            Problem::NoRoot =>
+                write!(f, "no bottom program"),
+            Problem::Cycle { ref names, ref lines } =>
+                write!(f, "lines {}: cycle {} -> {}", list(lines), names.join(" -> "), names[0]),
+            Problem::Orphan { name, line } =>
+                write!(f, "line {}: {} is not part of the tower", line, name),
+        }
+    }
+}
+
+/// Check that the input describes exactly one tree of programs.
+pub fn validate(input: &str) -> Result<(), Vec<Problem<'_>>> {
+    let mut problems = Vec::new();
+    let mut progs: HashMap<&str, Program> = HashMap::new();
+    let mut order = Vec::new();
+    let mut dups: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, line) in input.lines().enumerate() {
+        match parse_line(line, i + 1) {
+            None => problems.push(Problem::Malformed { line: i + 1 }),
+            Some((name, prog)) => {
+                if let Some(first) = progs.get(name) {
+                    dups.entry(name).or_insert_with(|| vec![first.line]).push(prog.line);
+                } else {
+                    order.push(name);
+                    progs.insert(name, prog);
+                }
+            },
+        }
+    }
+    for &name in &order {
+        if let Some(lines) = dups.remove(name) {
+            problems.push(Problem::Duplicate { name, lines });
+        }
+    }
+
+    // Parent lines for each child, in input order.
+    let mut parents: HashMap<&str, Vec<usize>> = HashMap::new();
+    for &name in &order {
+        let prog = &progs[name];
+        for &child in &prog.subprogs {
+            if progs.contains_key(child) {
+                parents.entry(child).or_default().push(prog.line);
+            } else {
+                problems.push(Problem::MissingChild { parent: name, child, line: prog.line });
+            }
+        }
+    }
+    for &name in &order {
+        if let Some(lines) = parents.get(name) {
+            if lines.len() > 1 {
+                problems.push(Problem::MultipleParents { child: name, lines: lines.clone() });
+            }
+        }
+    }
+
+    let roots: Vec<&str> = order.iter().cloned()
+        .filter(|n| !parents.contains_key(n))
+        .collect();
+    let (bases, loners): (Vec<&str>, Vec<&str>) = roots.iter()
+        .partition(|n| !progs[*n].subprogs.is_empty());
+    if order.is_empty() {
+        problems.push(Problem::Empty);
+    } else if roots.is_empty() {
+        problems.push(Problem::NoRoot);
+    } else if bases.len() > 1 {
+        problems.push(Problem::MultipleRoots {
+            lines: bases.iter().map(|n| progs[n].line).collect(),
+        });
+    }
+    if !bases.is_empty() || loners.len() > 1 {
+        for &name in &loners {
+            problems.push(Problem::Orphan { name, line: progs[name].line });
+        }
+    }
+
+    // Depth-first search for cycles; `path` is the current chain of
+    // programs being visited.
+    let mut done: HashMap<&str, bool> = HashMap::new();
+    for &start in &order {
+        if done.contains_key(start) {
+            continue;
+        }
+        let mut path: Vec<(&str, usize)> = vec![(start, 0)];
+        done.insert(start, false);
+        while let Some(&mut (name, ref mut next)) = path.last_mut() {
+            let subs = &progs[name].subprogs;
+            if *next == subs.len() {
+                done.insert(name, true);
+                path.pop();
+                continue;
+            }
+            let child = subs[*next];
+            *next += 1;
+            match done.get(child) {
+                _ if !progs.contains_key(child) => (),
+                None => {
+                    done.insert(child, false);
+                    path.push((child, 0));
+                },
+                Some(false) => {
+                    let at = path.iter().position(|&(n, _)| n == child).unwrap();
+                    let names: Vec<&str> = path[at..].iter().map(|&(n, _)| n).collect();
+                    let lines = names.iter().map(|n| progs[n].line).collect();
+                    problems.push(Problem::Cycle { names, lines });
+                },
+                Some(true) => (),
+            }
+        }
+    }
+
+    if problems.is_empty() { Ok(()) } else { Err(problems) }
 }
 
 fn find_total_weight(progs: &mut HashMap<&str, Program>, name: &str) -> u32 {
@@ -37,88 +194,346 @@ fn find_total_weight(progs: &mut HashMap<&str, Program>, name: &str) -> u32 {
     }
 }
 
-// return None if balanced, or Some((sub_prog_name,k)) if unbalanced
-// where k is required size
-fn check_subs<'a>(progs: &'a HashMap<&'a str, Program>, name: &'a str) -> Option<(&'a str,u32)> {
+/// Changing one program's weight, given as the path to it from the root.
+#[derive(Debug, PartialEq)]
+pub struct Fix<'a> {
+    pub path: Vec<&'a str>,
+    pub old_weight: u32,
+    pub new_weight: u32,
+}
+
+impl<'a> fmt::Display for Fix<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: change weight {} to {}",
+               self.path.join(" -> "), self.old_weight, self.new_weight)
+    }
+}
+
+/// A program whose sub-towers do not all weigh the same, with every
+/// single-weight change that would balance it.
+#[derive(Debug, PartialEq)]
+pub struct Imbalance<'a> {
+    pub path: Vec<&'a str>,
+    pub sub_weights: Vec<(&'a str, u32)>,
+    pub fixes: Vec<Fix<'a>>,
+}
+
+impl<'a> fmt::Display for Imbalance<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let subs: Vec<String> = self.sub_weights.iter()
+            .map(|&(n, w)| format!("{} {}", n, w))
+            .collect();
+        write!(f, "{} is unbalanced ({})", self.path.join(" -> "), subs.join(", "))
+    }
+}
+
+// Every way to change one weight in the tower at `name` so that it is
+// balanced and weighs `target` in total. `path` leads to `name`.
+fn fixes_to<'a>(progs: &HashMap<&'a str, Program<'a>>, path: &mut Vec<&'a str>,
+                name: &'a str, target: u32) -> Vec<Fix<'a>> {
     let prog = &progs[name];
+    let subs = &prog.subprogs;
+    let totals: Vec<u32> = subs.iter().map(|s| progs[s].total_weight).collect();
+    let mut fixes = Vec::new();
+    path.push(name);
 
-    // check for leaf -- balanced by definition
-    let num_subs = prog.subprogs.len() as u32;
-    if num_subs == 0 {
-        return None;
-    }
-
-    let sub_weights: Vec<u32> = prog.subprogs.iter().map(|&p| progs[p].total_weight).collect();
-    let total: u32 = sub_weights.iter().sum();
-    let min: u32 = *sub_weights.iter().min().unwrap();
-    let max: u32 = *sub_weights.iter().max().unwrap();
-
-    if total == num_subs * min {
-        // all balanced!
-        return None;
-    } else if total - num_subs * min > num_subs * max - total {
-        // one is too small
-        let it: &str = prog.subprogs.iter()
-            .filter(|&p| progs[p].total_weight == min)
-            .next().unwrap();
-        return Some((it, max));
+    if totals.windows(2).all(|w| w[0] == w[1]) {
+        // Already balanced: change this program's own weight...
+        let new_weight = prog.weight as i64 + target as i64 - prog.total_weight as i64;
+        if new_weight > 0 {
+            fixes.push(Fix {
+                path: path.clone(),
+                old_weight: prog.weight,
+                new_weight: new_weight as u32,
+            });
+        }
+        // ...or, if it holds just one sub-tower, anything in that.
+        if subs.len() == 1 && target > prog.weight {
+            fixes.extend(fixes_to(progs, path, subs[0], target - prog.weight));
+        }
     } else {
-        // one is too big
-        let it: &str = prog.subprogs.iter()
-            .filter(|&p| progs[p].total_weight == max)
-            .next().unwrap();
-        return Some((it, min));
-    }
-}
-
-fn resize(progs: &HashMap<&str, Program>, name: &str) -> u32 {
-    let mut curr = name;
-    let mut new_size = None;
-
-    loop {
-        match check_subs(progs, curr) {
-            None => {
-                match new_size {
-                    None => return 0,
-                    Some(k) => {
-                        let prog = &progs[curr];
-                        let sub_weight = prog.total_weight - prog.weight;
-                        let new_weight = k as i32 - sub_weight as i32;
-                        return new_weight.abs() as u32;
-                    },
-                }
-            },
-            Some((sub, k)) => {
-                new_size = Some(k);
-                curr = sub;
-            },
+        // The sub-towers must all come to the same weight, and exactly
+        // one of them must be off.
+        let k = subs.len() as u32;
+        if target > prog.weight && (target - prog.weight) % k == 0 {
+            let each = (target - prog.weight) / k;
+            let odd: Vec<usize> = (0..subs.len()).filter(|&i| totals[i] != each).collect();
+            if odd.len() == 1 {
+                fixes.extend(fixes_to(progs, path, subs[odd[0]], each));
+            }
+        }
+    }
+
+    path.pop();
+    fixes
+}
+
+// The sub-towers that could be the wrong one, each with the weight its
+// siblings agree on. A sub-tower is a candidate when all of its siblings
+// agree on a different weight; with two sub-towers both are candidates.
+fn odd_ones_out(totals: &[u32]) -> Vec<(usize, u32)> {
+    let mut odd = Vec::new();
+    for i in 0..totals.len() {
+        let mut others = totals.iter().enumerate()
+            .filter(|&(j, _)| j != i)
+            .map(|(_, &t)| t);
+        if let Some(first) = others.next() {
+            if first != totals[i] && others.all(|t| t == first) {
+                odd.push((i, first));
+            }
         }
     }
+    odd
 }
 
-fn solve(input: &str) -> (String, u32) {
+// Every unbalanced program, root first.
+fn imbalances<'a>(progs: &HashMap<&'a str, Program<'a>>, root: &'a str) -> Vec<Imbalance<'a>> {
+    let mut found = Vec::new();
+    let mut stack = vec![vec![root]];
+    while let Some(mut path) = stack.pop() {
+        let name = *path.last().unwrap();
+        let subs = &progs[name].subprogs;
+        let totals: Vec<u32> = subs.iter().map(|s| progs[s].total_weight).collect();
+
+        if !totals.windows(2).all(|w| w[0] == w[1]) {
+            let mut fixes = Vec::new();
+            for (i, target) in odd_ones_out(&totals) {
+                fixes.extend(fixes_to(progs, &mut path, subs[i], target));
+            }
+            found.push(Imbalance {
+                path: path.clone(),
+                sub_weights: subs.iter().cloned().zip(totals).collect(),
+                fixes,
+            });
+        }
+
+        for &sub in subs.iter().rev() {
+            let mut p = path.clone();
+            p.push(sub);
+            stack.push(p);
+        }
+    }
+    found
+}
+
+struct Tower<'a> {
+    root: &'a str,
+    progs: HashMap<&'a str, Program<'a>>,
+    imbalances: Vec<Imbalance<'a>>,
+}
+
+fn analyse(input: &str) -> Tower<'_> {
     let mut prog_list = parse_input(input);
     let mut rev_map: HashMap<&str, &str> = HashMap::new();
-    for (name, ref prog) in prog_list.iter() {
+    for (name, prog) in prog_list.iter() {
         for subname in prog.subprogs.iter() {
             rev_map.insert(subname, name);
         }
     }
-    let mut root: &str = rev_map.keys().next().unwrap();
-    while let Some(x) = rev_map.get(root) {
-        root = x;
-    }
+    let root: &str = prog_list.keys()
+        .find(|name| !rev_map.contains_key(*name))
+        .unwrap();
 
     find_total_weight(&mut prog_list, root);
-    let part2 = resize(&prog_list, root);
+    let imbalances = imbalances(&prog_list, root);
+    Tower { root, progs: prog_list, imbalances }
+}
+
+/// Why part 2 has no single answer.
+#[derive(Debug, PartialEq)]
+pub enum FixError {
+    Balanced,
+    /// No single weight change balances the tower.
+    NoFix,
+    /// More than one program could be the wrong one.
+    Ambiguous { fixes: usize },
+}
+
+impl fmt::Display for FixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FixError::Balanced =>
+                write!(f, "the tower is already balanced"),
+            FixError::NoFix =>
+                write!(f, "no single weight change balances the tower"),
+            FixError::Ambiguous { fixes } =>
+                write!(f, "{} different weight changes balance the tower", fixes),
+        }
+    }
+}
+
+// Any fix for the whole tower is one of the bottom imbalance's fixes,
+// so part 2 only has an answer when exactly one of those balances
+// everything else too.
+fn solve(tower: &Tower) -> (String, Result<u32, FixError>) {
+    let part2 = match tower.imbalances.first() {
+        None => Err(FixError::Balanced),
+        Some(im) => {
+            let fixes: Vec<&Fix> = im.fixes.iter().filter(|fix| tower.balances(fix)).collect();
+            match fixes.len() {
+                0 => Err(FixError::NoFix),
+                1 => Ok(fixes[0].new_weight),
+                n => Err(FixError::Ambiguous { fixes: n }),
+            }
+        },
+    };
+    (String::from(tower.root), part2)
+}
+
+impl<'a> Tower<'a> {
+    // Whether applying `fix` leaves every program balanced. Only the
+    // programs on its path change total weight, so every imbalance must
+    // be above the changed program, and the path itself rechecked.
+    fn balances(&self, fix: &Fix) -> bool {
+        let above = &fix.path[..fix.path.len() - 1];
+        let delta = fix.new_weight as i64 - fix.old_weight as i64;
+        let total = |name: &str| {
+            let t = self.progs[name].total_weight as i64;
+            if fix.path.contains(&name) { t + delta } else { t }
+        };
+        self.imbalances.iter().all(|im| above.starts_with(&im.path)) &&
+            above.iter().all(|&name| {
+                let subs = &self.progs[name].subprogs;
+                subs.windows(2).all(|w| total(w[0]) == total(w[1]))
+            })
+    }
+
+    fn is_unbalanced(&self, name: &str) -> bool {
+        self.imbalances.iter().any(|im| *im.path.last().unwrap() == name)
+    }
+
+    // Suggested new weights for a program, from any imbalance.
+    fn fixes_for(&self, name: &str) -> Vec<u32> {
+        let mut weights: Vec<u32> = self.imbalances.iter()
+            .flat_map(|im| im.fixes.iter())
+            .filter(|fix| *fix.path.last().unwrap() == name)
+            .map(|fix| fix.new_weight)
+            .collect();
+        weights.sort();
+        weights.dedup();
+        weights
+    }
+
+    // Every program in a sub-tower that could be the wrong one.
+    fn off_weight(&self) -> HashSet<&'a str> {
+        let mut stack = Vec::new();
+        for im in &self.imbalances {
+            let totals: Vec<u32> = im.sub_weights.iter().map(|&(_, w)| w).collect();
+            for (i, _) in odd_ones_out(&totals) {
+                stack.push(im.sub_weights[i].0);
+            }
+        }
+        let mut marked = HashSet::new();
+        while let Some(name) = stack.pop() {
+            if marked.insert(name) {
+                stack.extend(self.progs[name].subprogs.iter().cloned());
+            }
+        }
+        marked
+    }
+
+    // Programs in depth-first order from the root, with their depth.
+    fn walk(&self) -> Vec<(usize, &'a str)> {
+        let mut order = Vec::new();
+        let mut stack = vec![(0, self.root)];
+        while let Some((depth, name)) = stack.pop() {
+            order.push((depth, name));
+            for &sub in self.progs[name].subprogs.iter().rev() {
+                stack.push((depth + 1, sub));
+            }
+        }
+        order
+    }
 
-    (String::from(root), part2)
+    /// Graphviz source for the tower. Unbalanced programs are outlined in
+    /// red, everything in a sub-tower that could be the wrong one in
+    /// orange, and programs with a suggested fix are filled.
+    fn to_dot(&self) -> String {
+        let off_weight = self.off_weight();
+        let mut out = String::from("digraph tower {\n    node [shape=box];\n");
+        for (_, name) in self.walk() {
+            let prog = &self.progs[name];
+            let mut attrs = format!("label=\"{}\\nweight {}\\ntotal {}",
+                                    name, prog.weight, prog.total_weight);
+            let fixes = self.fixes_for(name);
+            if !fixes.is_empty() {
+                let ws: Vec<String> = fixes.iter().map(|w| w.to_string()).collect();
+                attrs += &format!("\\nfix: {}", ws.join(" or "));
+            }
+            attrs += "\"";
+            if self.is_unbalanced(name) {
+                attrs += ", color=red, penwidth=2";
+            } else if off_weight.contains(name) {
+                attrs += ", color=orange";
+            }
+            if !fixes.is_empty() {
+                attrs += ", style=filled, fillcolor=lightpink";
+            }
+            out += &format!("    \"{}\" [{}];\n", name, attrs);
+            for sub in &prog.subprogs {
+                out += &format!("    \"{}\" -> \"{}\";\n", name, sub);
+            }
+        }
+        out += "}\n";
+        out
+    }
+
+    /// The tower as an indented outline, one program per line.
+    fn to_tree(&self) -> String {
+        let mut out = String::new();
+        for (depth, name) in self.walk() {
+            let prog = &self.progs[name];
+            out += &format!("{:indent$}{} ({}) total {}", "", name, prog.weight,
+                            prog.total_weight, indent = 2 * depth);
+            if self.is_unbalanced(name) {
+                out += " [unbalanced]";
+            }
+            for w in self.fixes_for(name) {
+                out += &format!(" [fix: {}]", w);
+            }
+            out.push('\n');
+        }
+        out
+    }
 }
 
 pub fn run(input: &str) {
-    let (part1, part2) = solve(input);
+    if let Err(problems) = validate(input) {
+        println!("the input is not a valid tower:");
+        for p in problems {
+            println!("  {}", p);
+        }
+        return;
+    }
+    let tower = analyse(input);
+    let (part1, part2) = solve(&tower);
     println!("the solution to part 1 is {}", part1);
-    println!("the solution to part 2 is {}", part2);
+    match part2 {
+        Ok(weight) => println!("the solution to part 2 is {}", weight),
+        Err(e) => println!("there is no solution to part 2: {}", e),
+    }
+
+    for im in tower.imbalances {
+        println!("{}", im);
+        for fix in im.fixes {
+            println!("  fix: {}", fix);
+        }
+    }
+}
+
+/// Print the tower as `dot` (Graphviz) or as an indented `tree`.
+pub fn run_export(input: &str, format: &str) -> Result<(), String> {
+    if let Err(problems) = validate(input) {
+        let report: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+        return Err(format!("the input is not a valid tower:\n  {}", report.join("\n  ")));
+    }
+    let tower = analyse(input);
+    match format {
+        "dot" => print!("{}", tower.to_dot()),
+        "tree" => print!("{}", tower.to_tree()),
+        _ => return Err(format!("unknown export format '{}' (expected dot or tree)", format)),
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -144,8 +559,8 @@ cntj (57)
     #[test]
     fn parsing() {
         let test_map: HashMap<&str, Program> = vec![
-            ("pbga", Program{weight:66, total_weight:0, subprogs:vec![]}),
-            ("fwft", Program{weight:72, total_weight:0, subprogs:vec!["ktlj", "cntj", "xhth"]}),
+            ("pbga", Program{weight:66, total_weight:0, subprogs:vec![], line:1}),
+            ("fwft", Program{weight:72, total_weight:0, subprogs:vec!["ktlj", "cntj", "xhth"], line:2}),
             ].into_iter().collect();
         assert_eq!(parse_input("pbga (66)\nfwft (72) -> ktlj, cntj, xhth\n"),
                    test_map);
@@ -153,15 +568,197 @@ cntj (57)
 
     #[test]
     fn example1() {
-        let (part1, part2) = solve(EX_INPUT);
+        let (part1, part2) = solve(&analyse(EX_INPUT));
         assert_eq!("tknk", part1);
-        assert_eq!(60, part2);
+        assert_eq!(Ok(60), part2);
+    }
+
+    #[test]
+    fn valid() {
+        assert_eq!(Ok(()), validate(EX_INPUT));
+    }
+
+    #[test]
+    fn problems() {
+        let input = "\
+a (1) -> b, c
+b (2) -> d
+c (3) -> d, zz
+d (4)
+e (5)
+f (6 -> a
+g (7) -> h
+h (8) -> g
+k (9) -> e
+e (10)
+";
+        let problems = validate(input).unwrap_err();
+        assert_eq!(problems, vec![
+            Problem::Malformed { line: 6 },
+            Problem::Duplicate { name: "e", lines: vec![5, 10] },
+            Problem::MissingChild { parent: "c", child: "zz", line: 3 },
+            Problem::MultipleParents { child: "d", lines: vec![2, 3] },
+            Problem::MultipleRoots { lines: vec![1, 9] },
+            Problem::Cycle { names: vec!["g", "h"], lines: vec![7, 8] },
+        ]);
+        assert_eq!("lines 7, 8: cycle g -> h -> g", problems[5].to_string());
+        assert_eq!("line 3: c holds undefined program zz", problems[2].to_string());
+    }
+
+    #[test]
+    fn orphans_and_loops() {
+        let problems = validate("a (1) -> b\nb (2)\nc (3)\n").unwrap_err();
+        assert_eq!(problems, vec![Problem::Orphan { name: "c", line: 3 }]);
+
+        let problems = validate("a (1) -> b\nb (2) -> a\n").unwrap_err();
+        assert_eq!(problems, vec![
+            Problem::NoRoot,
+            Problem::Cycle { names: vec!["a", "b"], lines: vec![1, 2] },
+        ]);
+
+        assert_eq!(Ok(()), validate("a (1)\n"));
+    }
+
+    #[test]
+    fn empty_input() {
+        assert_eq!(Err(vec![Problem::Empty]), validate(""));
+        assert_eq!("no programs", Problem::Empty.to_string());
+        assert_eq!(Err(vec![Problem::Malformed { line: 1 }, Problem::Empty]), validate("\n"));
+        assert!(run_export("", "tree").is_err());
+    }
+
+    #[test]
+    fn example_imbalance() {
+        let found = analyse(EX_INPUT).imbalances;
+        assert_eq!(found, vec![Imbalance {
+            path: vec!["tknk"],
+            sub_weights: vec![("ugml", 251), ("padx", 243), ("fwft", 243)],
+            fixes: vec![Fix { path: vec!["tknk", "ugml"], old_weight: 68, new_weight: 60 }],
+        }]);
+        assert_eq!("tknk is unbalanced (ugml 251, padx 243, fwft 243)", found[0].to_string());
+        assert_eq!("tknk -> ugml: change weight 68 to 60", found[0].fixes[0].to_string());
+    }
+
+    #[test]
+    fn two_children() {
+        let found = analyse("r (1) -> a, b\na (5)\nb (7)\n").imbalances;
+        assert_eq!(found[0].fixes, vec![
+            Fix { path: vec!["r", "a"], old_weight: 5, new_weight: 7 },
+            Fix { path: vec!["r", "b"], old_weight: 7, new_weight: 5 },
+        ]);
+        assert_eq!(Err(FixError::Ambiguous { fixes: 2 }),
+                   solve(&analyse("r (1) -> a, b\na (5)\nb (7)\n")).1);
+        assert_eq!(Err(FixError::Balanced), solve(&analyse("r (1) -> a, b\na (5)\nb (5)\n")).1);
+    }
+
+    #[test]
+    fn every_imbalance() {
+        let input = "\
+r (1) -> a, b, c
+a (1) -> x, y, z
+b (7)
+c (7)
+x (2)
+y (2)
+z (3)
+d (2) -> e, f, g
+e (1)
+f (1)
+g (4)
+h (1) -> d, r
+";
+        let tower = analyse(input);
+        assert_eq!("h", tower.root);
+        let found = &tower.imbalances;
+        let paths: Vec<Vec<&str>> = found.iter().map(|im| im.path.clone()).collect();
+        assert_eq!(paths, vec![vec!["h"], vec!["h", "d"], vec!["h", "r"], vec!["h", "r", "a"]]);
+        // h's two sub-towers weigh 8 and 23, and neither can be brought
+        // to the other's weight with one change
+        assert_eq!(found[0].fixes, vec![]);
+        assert_eq!(found[1].fixes, vec![
+            Fix { path: vec!["h", "d", "g"], old_weight: 4, new_weight: 1 },
+        ]);
+        assert_eq!(found[2].fixes, vec![
+            Fix { path: vec!["h", "r", "a", "z"], old_weight: 3, new_weight: 2 },
+        ]);
+        assert_eq!(found[3].fixes, found[2].fixes);
+        assert_eq!(Err(FixError::NoFix), solve(&tower).1);
+
+        // a's weight balances r, but b stays unbalanced
+        let input = "r (1) -> a, b, c\na (6)\nb (1) -> x, y\nx (1)\ny (3)\nc (5)\n";
+        assert_eq!(Ok(()), validate(input));
+        let tower = analyse(input);
+        assert_eq!(tower.imbalances[0].fixes, vec![
+            Fix { path: vec!["r", "a"], old_weight: 6, new_weight: 5 },
+        ]);
+        assert_eq!(Err(FixError::NoFix), solve(&tower).1);
+
+        // s is balanced, so changing anything under t unbalances it
+        let tower = analyse("s (1) -> t, u\nt (1) -> x, y, z\nu (8)\nx (2)\ny (2)\nz (3)\n");
+        assert_eq!(tower.imbalances[0].path, vec!["s", "t"]);
+        assert_eq!(tower.imbalances[0].fixes.len(), 1);
+        assert_eq!(Err(FixError::NoFix), solve(&tower).1);
+        // unless it is the only thing s holds
+        let tower = analyse("s (1) -> t\nt (1) -> x, y, z\nx (2)\ny (2)\nz (3)\n");
+        assert_eq!(Ok(2), solve(&tower).1);
+    }
+
+    #[test]
+    fn export_tree() {
+        assert_eq!(analyse(EX_INPUT).to_tree(), "\
+tknk (41) total 778 [unbalanced]
+  ugml (68) total 251 [fix: 60]
+    gyxo (61) total 61
+    ebii (61) total 61
+    jptl (61) total 61
+  padx (45) total 243
+    pbga (66) total 66
+    havc (66) total 66
+    qoyq (66) total 66
+  fwft (72) total 243
+    ktlj (57) total 57
+    cntj (57) total 57
+    xhth (57) total 57
+");
+    }
+
+    #[test]
+    fn export_dot() {
+        let dot = analyse("r (1) -> a, b\na (5)\nb (7)\n").to_dot();
+        assert_eq!(dot, "\
+digraph tower {
+    node [shape=box];
+    \"r\" [label=\"r\\nweight 1\\ntotal 13\", color=red, penwidth=2];
+    \"r\" -> \"a\";
+    \"r\" -> \"b\";
+    \"a\" [label=\"a\\nweight 5\\ntotal 5\\nfix: 7\", color=orange, style=filled, fillcolor=lightpink];
+    \"b\" [label=\"b\\nweight 7\\ntotal 7\\nfix: 5\", color=orange, style=filled, fillcolor=lightpink];
+}
+");
+
+        // the whole of a's sub-tower is marked, not just the fix
+        let dot = analyse("r (1) -> a, b, c\na (1) -> x\nb (3)\nc (3)\nx (4)\n").to_dot();
+        assert_eq!(dot, "\
+digraph tower {
+    node [shape=box];
+    \"r\" [label=\"r\\nweight 1\\ntotal 12\", color=red, penwidth=2];
+    \"r\" -> \"a\";
+    \"r\" -> \"b\";
+    \"r\" -> \"c\";
+    \"a\" [label=\"a\\nweight 1\\ntotal 5\", color=orange];
+    \"a\" -> \"x\";
+    \"x\" [label=\"x\\nweight 4\\ntotal 4\\nfix: 2\", color=orange, style=filled, fillcolor=lightpink];
+    \"b\" [label=\"b\\nweight 3\\ntotal 3\"];
+    \"c\" [label=\"c\\nweight 3\\ntotal 3\"];
+}
+");
     }
 
     #[test]
     fn real_input() {
         let input = include_str!("../inputs/day07.txt");
-        let x = solve(&input);
+        let (part1, part2) = solve(&analyse(input));
+        let x = (part1, part2.unwrap());
         assert_eq!(include_str!("../outputs/day07.txt"),
                    format!("{:?}", x));
     }
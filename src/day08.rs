@@ -1,27 +1,74 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::time::Instant;
+use rng::XorShift32;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 struct Instr<'a> {
     reg: &'a str,
-    arg: i32,
+    arg: i64,
     creg: &'a str,
     cop: CmpOp,
-    carg: i32,
+    carg: i64,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 enum CmpOp { LT, LE, EQ, NE, GE, GT }
 use self::CmpOp::*;
 
+impl<'a> fmt::Display for Instr<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = if self.arg < 0 { "dec" } else { "inc" };
+        write!(f, "{} {} {} if {} {} {}", self.reg, op, self.arg.unsigned_abs(),
+               self.creg, self.cop.symbol(), self.carg)
+    }
+}
+
+impl CmpOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            LT => "<",
+            LE => "<=",
+            EQ => "==",
+            NE => "!=",
+            GE => ">=",
+            GT => ">",
+        }
+    }
+
+    fn negate(self) -> CmpOp {
+        match self {
+            LT => GE,
+            LE => GT,
+            EQ => NE,
+            NE => EQ,
+            GE => LT,
+            GT => LE,
+        }
+    }
+
+    fn holds(self, a: i64, b: i64) -> bool {
+        match self {
+            LT => a < b,
+            LE => a <= b,
+            EQ => a == b,
+            NE => a != b,
+            GE => a >= b,
+            GT => a > b,
+        }
+    }
+}
+
 fn parse_input(s: &str) -> Vec<Instr> {
     s.lines().map(|line| {
             let mut s = line.split_whitespace();
             let reg = s.next().unwrap();
             let op = s.next().unwrap();
-            let val: i32 = s.next().unwrap().parse().unwrap();
+            let val: i64 = s.next().unwrap().parse().unwrap();
             let arg = match op {
                 "inc" => val,
-                "dec" => -val,
+                "dec" => val.checked_neg().expect("decrement too large"),
                 _ => panic!("unknown operation: {}", op)
             };
             s.next(); // drop "if"
@@ -41,36 +88,664 @@ fn parse_input(s: &str) -> Vec<Instr> {
         }).collect()
 }
 
-fn solve(input: &str) -> (i32, i32) {
-    let instrs = parse_input(input);
-    let mut regs = HashMap::new();
-    let mut max = 0;
-
-    for i in instrs {
-        let creg: i32 = *regs.entry(i.creg).or_insert(0);
-        let cond = match i.cop {
-            LT => creg < i.carg,
-            LE => creg <= i.carg,
-            EQ => creg == i.carg,
-            NE => creg != i.carg,
-            GE => creg >= i.carg,
-            GT => creg > i.carg,
+// One compiled instruction, with registers as indices into a dense
+// register file.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Op {
+    reg: u32,
+    arg: i64,
+    creg: u32,
+    cop: CmpOp,
+    carg: i64,
+}
+
+#[derive(Debug, PartialEq)]
+struct Compiled<'a> {
+    names: Vec<&'a str>,
+    code: Vec<Op>,
+}
+
+/// A register went past the range of an i64 at the given instruction.
+#[derive(Debug, PartialEq)]
+struct Overflow {
+    instr: usize,
+    reg: String,
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "register {} overflowed at instruction {}", self.reg, self.instr + 1)
+    }
+}
+
+// Registers are numbered in order of first appearance.
+fn compile<'a>(instrs: &[Instr<'a>]) -> Compiled<'a> {
+    let mut index = HashMap::new();
+    let mut names = Vec::new();
+    let mut intern = |name: &'a str| {
+        *index.entry(name).or_insert_with(|| {
+            names.push(name);
+            names.len() as u32 - 1
+        })
+    };
+    let code = instrs.iter()
+        .map(|i| {
+            let reg = intern(i.reg);
+            let creg = intern(i.creg);
+            Op { reg, arg: i.arg, creg, cop: i.cop, carg: i.carg }
+        })
+        .collect();
+    Compiled { names, code }
+}
+
+// The registers after a run.
+#[derive(Debug, PartialEq)]
+struct Run<'a> {
+    names: Vec<&'a str>,
+    regs: Vec<i64>,
+    // whether each register was read or written; as in the puzzle, an
+    // update that never runs does not bring its register into being
+    used: Vec<bool>,
+    // the highest value ever held (at least 0, since every register
+    // starts there)
+    highest: i64,
+    // the register and instruction that first went above 0 to `highest`
+    peak: Option<(u32, usize)>,
+}
+
+impl<'a> Run<'a> {
+    // The largest final value of a register that was used.
+    fn largest(&self) -> Option<i64> {
+        self.regs.iter().zip(&self.used)
+            .filter(|&(_, &used)| used)
+            .map(|(&v, _)| v)
+            .max()
+    }
+}
+
+impl<'a> Compiled<'a> {
+    fn execute(&self) -> Result<Run<'a>, Overflow> {
+        let mut regs = vec![0; self.names.len()];
+        let mut used = vec![false; self.names.len()];
+        let (mut highest, mut peak) = (0, None);
+        for (k, op) in self.code.iter().enumerate() {
+            used[op.creg as usize] = true;
+            if op.cop.holds(regs[op.creg as usize], op.carg) {
+                used[op.reg as usize] = true;
+                let reg = &mut regs[op.reg as usize];
+                *reg = reg.checked_add(op.arg).ok_or_else(|| {
+                    Overflow { instr: k, reg: self.names[op.reg as usize].to_string() }
+                })?;
+                if *reg > highest {
+                    highest = *reg;
+                    peak = Some((op.reg, k));
+                }
+            }
+        }
+        Ok(Run { names: self.names.clone(), regs, used, highest, peak })
+    }
+}
+
+/// How often one distinct condition was checked and how often it held.
+#[derive(Debug, Clone, PartialEq)]
+struct CondCount {
+    creg: u32,
+    cop: CmpOp,
+    carg: i64,
+    checked: u32,
+    fired: u32,
+}
+
+/// Everything that happened to the registers during a run.
+#[derive(Debug, PartialEq)]
+struct History<'a> {
+    names: Vec<&'a str>,
+    // per register, each instruction that changed it and the new value
+    timelines: Vec<Vec<(usize, i64)>>,
+    // in order of first appearance
+    conditions: Vec<CondCount>,
+}
+
+impl<'a> Compiled<'a> {
+    fn trace(&self) -> Result<History<'a>, Overflow> {
+        let mut regs = vec![0i64; self.names.len()];
+        let mut timelines = vec![Vec::new(); self.names.len()];
+        let mut conditions: Vec<CondCount> = Vec::new();
+        let mut cond_index = HashMap::new();
+        for (k, op) in self.code.iter().enumerate() {
+            let c = *cond_index.entry((op.creg, op.cop, op.carg)).or_insert_with(|| {
+                conditions.push(CondCount {
+                    creg: op.creg, cop: op.cop, carg: op.carg, checked: 0, fired: 0,
+                });
+                conditions.len() - 1
+            });
+            conditions[c].checked += 1;
+            if op.cop.holds(regs[op.creg as usize], op.carg) {
+                conditions[c].fired += 1;
+                let reg = &mut regs[op.reg as usize];
+                *reg = reg.checked_add(op.arg).ok_or_else(|| {
+                    Overflow { instr: k, reg: self.names[op.reg as usize].to_string() }
+                })?;
+                timelines[op.reg as usize].push((k, *reg));
+            }
+        }
+        Ok(History { names: self.names.clone(), timelines, conditions })
+    }
+}
+
+impl<'a> History<'a> {
+    // The lowest and highest values of a register, each with the first
+    // instruction that produced it, or None for the starting 0.
+    fn extremes(&self, reg: usize) -> ((i64, Option<usize>), (i64, Option<usize>)) {
+        let (mut min, mut max) = ((0, None), (0, None));
+        for &(k, v) in &self.timelines[reg] {
+            if v < min.0 {
+                min = (v, Some(k));
+            }
+            if v > max.0 {
+                max = (v, Some(k));
+            }
+        }
+        (min, max)
+    }
+
+    /// `instruction,register,value`, one row per change, in execution
+    /// order. Instructions are numbered from 1.
+    fn timeline_csv(&self) -> String {
+        let mut rows: Vec<(usize, usize, i64)> = self.timelines.iter().enumerate()
+            .flat_map(|(r, t)| t.iter().map(move |&(k, v)| (k, r, v)))
+            .collect();
+        rows.sort();
+        let mut out = String::from("instruction,register,value\n");
+        for (k, r, v) in rows {
+            out += &format!("{},{},{}\n", k + 1, self.names[r], v);
+        }
+        out
+    }
+
+    /// One row per register. The `_at` columns are empty where the value
+    /// is the starting 0.
+    fn registers_csv(&self) -> String {
+        let at = |k: Option<usize>| k.map_or(String::new(), |k| (k + 1).to_string());
+        let mut out = String::from("register,final,changes,min,min_at,max,max_at\n");
+        for (r, name) in self.names.iter().enumerate() {
+            let last = self.timelines[r].last().map_or(0, |&(_, v)| v);
+            let (min, max) = self.extremes(r);
+            out += &format!("{},{},{},{},{},{},{}\n", name, last, self.timelines[r].len(),
+                            min.0, at(min.1), max.0, at(max.1));
+        }
+        out
+    }
+
+    /// One row per distinct condition.
+    fn conditions_csv(&self) -> String {
+        let mut out = String::from("condition,checked,fired\n");
+        for c in &self.conditions {
+            out += &format!("{} {} {},{},{}\n", self.names[c.creg as usize],
+                            c.cop.symbol(), c.carg, c.checked, c.fired);
+        }
+        out
+    }
+}
+
+// The values a register might hold, as an inclusive range.
+type Interval = (i64, i64);
+
+// The part of `range` for which `x cop carg` holds, if any.
+fn restrict(range: Interval, cop: CmpOp, carg: i64) -> Option<Interval> {
+    let (lo, hi) = range;
+    let (lo, hi) = match cop {
+        LT => (lo, hi.min(carg.saturating_sub(1))),
+        LE => (lo, hi.min(carg)),
+        EQ => (lo.max(carg), hi.min(carg)),
+        GE => (lo.max(carg), hi),
+        GT => (lo.max(carg.saturating_add(1)), hi),
+        NE if lo == carg && hi == carg => return None,
+        NE if lo == carg => (lo + 1, hi),
+        NE if hi == carg => (lo, hi - 1),
+        NE => (lo, hi),
+    };
+    if lo <= hi { Some((lo, hi)) } else { None }
+}
+
+/// Whether an instruction's condition can be decided without running.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Verdict {
+    Always,
+    Never,
+    Depends,
+}
+
+#[derive(Debug, PartialEq)]
+struct Analysis<'a> {
+    /// For each instruction.
+    verdicts: Vec<Verdict>,
+    /// Each register's possible values at the end.
+    ranges: Vec<(&'a str, Interval)>,
+    /// Never written by an instruction that can run, so always 0.
+    always_zero: Vec<&'a str>,
+    /// Never read by any condition, so they can't affect which
+    /// instructions run.
+    unconditioned: Vec<&'a str>,
+    /// The program without instructions that can never run or change
+    /// nothing. Every register ends with the same value as in the original
+    /// (reading as 0 if it no longer appears).
+    simplified: Vec<Instr<'a>>,
+}
+
+// Straight-line code makes this a single pass: the range of each register
+// is narrowed by conditions on it and widened by updates that might run.
+fn analyse<'a>(instrs: &[Instr<'a>]) -> Analysis<'a> {
+    let compiled = compile(instrs);
+    let mut ranges = vec![(0, 0); compiled.names.len()];
+    let mut verdicts = Vec::with_capacity(instrs.len());
+    for op in &compiled.code {
+        let range = ranges[op.creg as usize];
+        let taken = restrict(range, op.cop, op.carg);
+        let verdict = match (taken, restrict(range, op.cop.negate(), op.carg)) {
+            (None, _) => Verdict::Never,
+            (_, None) => Verdict::Always,
+            _ => Verdict::Depends,
+        };
+        if let Some(taken) = taken {
+            // if the register is its own condition, only the values that
+            // pass the test get updated
+            let before = if op.reg == op.creg { taken } else { ranges[op.reg as usize] };
+            let after = (before.0.saturating_add(op.arg), before.1.saturating_add(op.arg));
+            let old = ranges[op.reg as usize];
+            ranges[op.reg as usize] = match verdict {
+                Verdict::Always => after,
+                _ => (old.0.min(after.0), old.1.max(after.1)),
+            };
+        }
+        verdicts.push(verdict);
+    }
+
+    let simplified: Vec<Instr> = instrs.iter().zip(&verdicts)
+        .filter(|&(i, &v)| v != Verdict::Never && i.arg != 0)
+        .map(|(i, _)| i.clone())
+        .collect();
+    let written: Vec<&str> = simplified.iter().map(|i| i.reg).collect();
+    let read: Vec<&str> = instrs.iter().map(|i| i.creg).collect();
+    let names = &compiled.names;
+    Analysis {
+        verdicts,
+        ranges: names.iter().cloned().zip(ranges).collect(),
+        always_zero: names.iter().cloned().filter(|r| !written.contains(r)).collect(),
+        unconditioned: names.iter().cloned().filter(|r| !read.contains(r)).collect(),
+        simplified,
+    }
+}
+
+fn run_program(input: &str) -> Run<'_> {
+    compile(&parse_input(input)).execute()
+        .unwrap_or_else(|e| panic!("{}", e))
+}
+
+fn solve(run: &Run) -> (i64, i64) {
+    (run.largest().unwrap(), run.highest)
+}
+
+// A fixed pseudo-random program for timing runs, over `nregs` registers.
+fn bench_program(lines: usize, nregs: usize) -> String {
+    let mut rng = XorShift32::new(0x2017_0008);
+    let mut next = || rng.next_u32() as usize;
+    let mut out = String::new();
+    for _ in 0..lines {
+        let (reg, creg) = (next() % nregs, next() % nregs);
+        let op = ["inc", "dec"][next() % 2];
+        let cop = ["<", "<=", "==", "!=", ">=", ">"][next() % 6];
+        let (arg, carg) = (next() % 2000, next() % 2000);
+        out += &format!("r{} {} {} if r{} {} {}\n",
+                        reg, op, arg as i64 - 1000, creg, cop, carg as i64 - 1000);
+    }
+    out
+}
+
+/// The register language, extended beyond the puzzle: both sides of an
+/// update or comparison can be arbitrary expressions over registers,
+/// conditions can be combined with `&&` and `||`, and `print` shows values.
+///
+/// ```text
+/// a inc b * 2 if c > d && e != 0
+/// print a, a + b
+/// print
+/// ```
+pub mod lang {
+    use std::collections::BTreeMap;
+    use std::fmt;
+    use std::io::{self, BufRead, Write};
+    use std::str::FromStr;
+    use expr::{self, Parser, Token};
+    pub use expr::{EvalError, Expr};
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ParseError {
+        pub line: usize,
+        pub pos: usize,
+        pub msg: String,
+    }
+
+    impl fmt::Display for ParseError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "line {}, column {}: {}", self.line + 1, self.pos + 1, self.msg)
+        }
+    }
+
+    impl From<expr::ParseError> for ParseError {
+        fn from(e: expr::ParseError) -> ParseError {
+            ParseError { line: 0, pos: e.pos, msg: e.msg }
+        }
+    }
+
+    /// One line of a program.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Stmt {
+        /// `reg inc expr [if cond]`; `dec` is parsed as a negated `inc`.
+        Update { reg: String, delta: Expr, cond: Option<Expr> },
+        /// `print expr, ...`, or every register for a bare `print`.
+        Print(Vec<Expr>),
+    }
+
+    // Keywords are only special where a keyword can appear, so any
+    // name the puzzle uses for a register still works.
+    fn stmt(p: &mut Parser) -> Result<Stmt, expr::ParseError> {
+        let verb = match p.peek_at(1) {
+            Some(Token::Ident(w)) if w == "inc" || w == "dec" => Some(w),
+            _ => None,
         };
-        if cond {
-            let reg = regs.entry(i.reg).or_insert(0);
-            *reg += i.arg;
-            if *reg > max {
-                max = *reg;
+        match (p.peek(), verb) {
+            (Some(Token::Ident(reg)), Some(verb)) => {
+                p.advance(2);
+                let mut delta = p.expr()?;
+                if verb == "dec" {
+                    delta = Expr::Unary("-", Box::new(delta));
+                }
+                let cond = if p.peek() == Some(Token::Ident("if")) {
+                    p.advance(1);
+                    Some(p.expr()?)
+                } else {
+                    None
+                };
+                Ok(Stmt::Update { reg: reg.to_string(), delta, cond })
+            },
+            (Some(Token::Ident("print")), None) => {
+                p.advance(1);
+                let mut args = Vec::new();
+                if p.peek().is_some() {
+                    args.push(p.expr()?);
+                    while p.peek() == Some(Token::Comma) {
+                        p.advance(1);
+                        args.push(p.expr()?);
+                    }
+                }
+                Ok(Stmt::Print(args))
+            },
+            _ => expr::error(p.pos(), "expected 'reg inc ...', 'reg dec ...' or 'print'"),
+        }
+    }
+
+    impl FromStr for Stmt {
+        type Err = ParseError;
+
+        // Anything after a `#` is a comment.
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let code = s.split('#').next().unwrap();
+            let mut parser = Parser::new(code)?;
+            let stmt = stmt(&mut parser)?;
+            parser.finish("statement")?;
+            Ok(stmt)
+        }
+    }
+
+    /// A parsed program: statements with their (0-based) line numbers.
+    /// Blank lines and `#` comments are skipped.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Program {
+        pub stmts: Vec<(usize, Stmt)>,
+    }
+
+    impl FromStr for Program {
+        type Err = ParseError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let mut stmts = Vec::new();
+            for (n, line) in s.lines().enumerate() {
+                let code = line.split('#').next().unwrap();
+                if code.trim().is_empty() {
+                    continue;
+                }
+                let stmt = code.parse().map_err(|e| ParseError { line: n, ..e })?;
+                stmts.push((n, stmt));
+            }
+            Ok(Program { stmts })
+        }
+    }
+
+    /// An evaluation error and the line it happened on.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct RunError {
+        pub line: usize,
+        pub err: EvalError,
+    }
+
+    impl fmt::Display for RunError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "line {}: {}", self.line + 1, self.err)
+        }
+    }
+
+    /// What executing a statement did.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Effect {
+        Set { reg: String, value: i64 },
+        Skipped,
+        Printed(String),
+    }
+
+    /// Register state. As in the puzzle, a register exists, starting at
+    /// 0, once a statement reads or writes it. An update whose condition
+    /// is false only reads the condition.
+    #[derive(Debug, Default)]
+    pub struct Machine {
+        regs: BTreeMap<String, i64>,
+        highest: i64,
+    }
+
+    impl Machine {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn get(&self, reg: &str) -> i64 {
+            self.regs.get(reg).cloned().unwrap_or(0)
+        }
+
+        pub fn registers(&self) -> &BTreeMap<String, i64> {
+            &self.regs
+        }
+
+        /// The largest value currently in a register.
+        pub fn largest(&self) -> Option<i64> {
+            self.regs.values().max().cloned()
+        }
+
+        /// The highest value any register has held (at least 0).
+        pub fn highest(&self) -> i64 {
+            self.highest
+        }
+
+        fn eval(&self, e: &Expr) -> Result<i64, EvalError> {
+            e.eval(&|r| self.get(r))
+        }
+
+        fn touch(&mut self, e: &Expr) {
+            let mut names = Vec::new();
+            e.vars(&mut names);
+            for r in names {
+                if !self.regs.contains_key(r) {
+                    self.regs.insert(r.to_string(), 0);
+                }
+            }
+        }
+
+        /// Execute one statement. On error the registers are unchanged.
+        pub fn exec(&mut self, stmt: &Stmt) -> Result<Effect, EvalError> {
+            match *stmt {
+                Stmt::Update { ref reg, ref delta, ref cond } => {
+                    if let Some(ref c) = *cond {
+                        if self.eval(c)? == 0 {
+                            self.touch(c);
+                            return Ok(Effect::Skipped);
+                        }
+                    }
+                    let d = self.eval(delta)?;
+                    let value = self.get(reg).checked_add(d).ok_or(EvalError::Overflow)?;
+                    if let Some(ref c) = *cond {
+                        self.touch(c);
+                    }
+                    self.touch(delta);
+                    self.regs.insert(reg.clone(), value);
+                    self.highest = self.highest.max(value);
+                    Ok(Effect::Set { reg: reg.clone(), value })
+                },
+                Stmt::Print(ref args) if args.is_empty() => {
+                    let lines: Vec<String> = self.regs.iter()
+                        .map(|(r, v)| format!("{} = {}\n", r, v))
+                        .collect();
+                    Ok(Effect::Printed(lines.concat()))
+                },
+                Stmt::Print(ref args) => {
+                    let values = args.iter()
+                        .map(|e| self.eval(e).map(|v| v.to_string()))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Ok(Effect::Printed(values.join(" ") + "\n"))
+                },
+            }
+        }
+
+        /// Execute a whole program, passing anything it prints to `print`.
+        pub fn run<F: FnMut(&str)>(&mut self, prog: &Program, mut print: F)
+            -> Result<(), RunError>
+        {
+            for &(line, ref stmt) in &prog.stmts {
+                match self.exec(stmt) {
+                    Ok(Effect::Printed(text)) => print(&text),
+                    Ok(_) => {},
+                    Err(err) => return Err(RunError { line, err }),
+                }
             }
+            Ok(())
         }
     }
-    (*regs.values().max().unwrap(), max)
+
+    /// Read statements from `input` one line at a time, execute them and
+    /// report what changed. Errors are reported and the session continues.
+    pub fn repl<R: BufRead, W: Write>(machine: &mut Machine, input: R, mut out: W)
+        -> io::Result<()>
+    {
+        write!(out, "> ")?;
+        out.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            if !line.split('#').next().unwrap().trim().is_empty() {
+                match line.parse::<Stmt>().map(|stmt| machine.exec(&stmt)) {
+                    Err(e) => writeln!(out, "parse error at column {}: {}", e.pos + 1, e.msg)?,
+                    Ok(Err(e)) => writeln!(out, "error: {}", e)?,
+                    Ok(Ok(Effect::Set { reg, value })) => writeln!(out, "{} = {}", reg, value)?,
+                    Ok(Ok(Effect::Skipped)) => writeln!(out, "(condition false)")?,
+                    Ok(Ok(Effect::Printed(text))) => write!(out, "{}", text)?,
+                }
+            }
+            write!(out, "> ")?;
+            out.flush()?;
+        }
+        writeln!(out)
+    }
 }
 
 pub fn run(input: &str) {
-    let (part1, part2) = solve(input);
+    let run = run_program(input);
+    let (part1, part2) = solve(&run);
     println!("the largest value in a register is {}", part1);
     println!("the highest value ever held during processing is {}", part2);
+    if let Some((reg, k)) = run.peak {
+        println!("  ({} reached {} at instruction {})", run.names[reg as usize], part2, k + 1);
+    }
+}
+
+// Time parsing, compiling and running a generated program with a million
+// instructions over a thousand registers.
+fn run_bench() {
+    let program = bench_program(1_000_000, 1000);
+    let now = Instant::now();
+    let instrs = parse_input(&program);
+    let compiled = compile(&instrs);
+    let parsed = now.elapsed();
+    let result = compiled.execute().map(|run| run.highest);
+    let e = now.elapsed();
+    println!("1000000 instructions over {} registers (highest {:?}): \
+              parsed and compiled in {}.{:03}s, total {}.{:03}s",
+             compiled.names.len(), result, parsed.as_secs(), parsed.subsec_millis(),
+             e.as_secs(), e.subsec_millis());
+}
+
+/// Run the puzzle input in another mode:
+///
+/// * `exec`: as a program in the extended language, printing what it prints
+/// * `repl`: the same, then continue with statements read from stdin
+/// * `timeline`, `registers`, `conditions`: a CSV of the run's history
+/// * `analyse`: what can be worked out without running, and a simplified
+///   program
+/// * `bench`: ignore the input and time a generated million-line program
+pub fn run_mode(input: &str, mode: &str) -> Result<(), String> {
+    match mode {
+        "exec" | "repl" => run_lang(input, mode),
+        "timeline" | "registers" | "conditions" => {
+            let instrs = parse_input(input);
+            let history = compile(&instrs).trace().map_err(|e| e.to_string())?;
+            print!("{}", match mode {
+                "timeline" => history.timeline_csv(),
+                "registers" => history.registers_csv(),
+                _ => history.conditions_csv(),
+            });
+            Ok(())
+        },
+        "analyse" => {
+            let analysis = analyse(&parse_input(input));
+            println!("# always 0: {}", analysis.always_zero.join(" "));
+            println!("# never read by a condition: {}", analysis.unconditioned.join(" "));
+            for (k, v) in analysis.verdicts.iter().enumerate() {
+                if *v != Verdict::Depends {
+                    println!("# instruction {}: condition is {:?} true", k + 1, v);
+                }
+            }
+            for &(reg, (lo, hi)) in &analysis.ranges {
+                println!("# {} ends in {}..={}", reg, lo, hi);
+            }
+            for instr in &analysis.simplified {
+                println!("{}", instr);
+            }
+            Ok(())
+        },
+        "bench" => {
+            run_bench();
+            Ok(())
+        },
+        _ => Err(format!("unknown mode '{}' (expected exec, repl, timeline, \
+                          registers, conditions, analyse or bench)", mode)),
+    }
+}
+
+fn run_lang(input: &str, mode: &str) -> Result<(), String> {
+    let prog: lang::Program = input.parse().map_err(|e| format!("parse error at {}", e))?;
+    let mut machine = lang::Machine::new();
+    machine.run(&prog, |text| print!("{}", text)).map_err(|e| e.to_string())?;
+    if mode == "repl" {
+        let stdin = io::stdin();
+        lang::repl(&mut machine, stdin.lock(), io::stdout()).map_err(|e| e.to_string())?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -97,15 +772,265 @@ c inc -20 if c == 10
 
     #[test]
     fn example1() {
-        let (part1,part2) = solve(EXAMPLE);
+        let (part1,part2) = solve(&run_program(EXAMPLE));
         assert_eq!(1, part1);
         assert_eq!(10, part2);
     }
 
+    // The original interpreter, looking registers up by name.
+    fn solve_slowly(input: &str) -> (i64, i64) {
+        let mut regs = HashMap::new();
+        let mut max = 0;
+        for i in parse_input(input) {
+            let creg: i64 = *regs.entry(i.creg).or_insert(0);
+            if i.cop.holds(creg, i.carg) {
+                let reg = regs.entry(i.reg).or_insert(0);
+                *reg += i.arg;
+                max = max.max(*reg);
+            }
+        }
+        (*regs.values().max().unwrap(), max)
+    }
+
+    #[test]
+    fn compiled() {
+        let instrs = parse_input(EXAMPLE);
+        let compiled = compile(&instrs);
+        assert_eq!(vec!["b", "a", "c"], compiled.names);
+        assert_eq!(Op { reg: 2, arg: -20, creg: 2, cop: EQ, carg: 10 }, compiled.code[3]);
+        let run = compiled.execute().unwrap();
+        assert_eq!(vec![0, 1, -10], run.regs);
+        assert_eq!((Some(1), 10), (run.largest(), run.highest));
+        assert_eq!(Some((2, 2)), run.peak);
+
+        for &(lines, nregs) in &[(1, 1), (50, 3), (1000, 10), (20000, 200)] {
+            let program = bench_program(lines, nregs);
+            assert_eq!(solve_slowly(&program), solve(&run_program(&program)));
+        }
+
+        // a is never written, so it doesn't count towards part 1
+        let program = "a inc 1 if b > 5\nb dec 1 if b == 0\n";
+        assert_eq!((-1, 0), solve_slowly(program));
+        assert_eq!((-1, 0), solve(&run_program(program)));
+    }
+
+    #[test]
+    fn wide_values() {
+        let (part1, part2) = solve(&run_program("a inc 3000000000 if b == 0\na inc 3000000000 if a > 0\n"));
+        assert_eq!((6_000_000_000, 6_000_000_000), (part1, part2));
+
+        let instrs = parse_input("a inc 9223372036854775807 if a == 0\nx dec 5 if a > 1\na inc 1 if x < 0\n");
+        assert_eq!(Err(Overflow { instr: 2, reg: "a".to_string() }), compile(&instrs).execute());
+    }
+
+    #[test]
+    fn history() {
+        let instrs = parse_input(EXAMPLE);
+        let history = compile(&instrs).trace().unwrap();
+        assert_eq!(vec![vec![], vec![(1, 1)], vec![(2, 10), (3, -10)]], history.timelines);
+        assert_eq!(((-10, Some(3)), (10, Some(2))), history.extremes(2));
+        assert_eq!(((0, None), (0, None)), history.extremes(0));
+        assert_eq!(history.timeline_csv(), "\
+instruction,register,value
+2,a,1
+3,c,10
+4,c,-10
+");
+        assert_eq!(history.registers_csv(), "\
+register,final,changes,min,min_at,max,max_at
+b,0,0,0,,0,
+a,1,1,0,,1,2
+c,-10,2,-10,4,10,3
+");
+        assert_eq!(history.conditions_csv(), "\
+condition,checked,fired
+a > 1,1,0
+b < 5,1,1
+a >= 1,1,1
+c == 10,1,1
+");
+
+        // repeated conditions are counted together
+        let instrs = parse_input("a inc 1 if b == 0\nb inc 1 if a > 0\na inc 1 if b == 0\n");
+        let history = compile(&instrs).trace().unwrap();
+        assert_eq!("condition,checked,fired\nb == 0,2,1\na > 0,1,1\n",
+                   history.conditions_csv());
+
+        let program = bench_program(5000, 20);
+        let instrs = parse_input(&program);
+        let compiled = compile(&instrs);
+        let history = compiled.trace().unwrap();
+        let run = compiled.execute().unwrap();
+        let (reg, k) = run.peak.unwrap();
+        assert!(history.timelines[reg as usize].contains(&(k, run.highest)));
+        assert!(history.timelines.iter().flatten().all(|&(j, v)| j >= k || v < run.highest));
+        for (r, &v) in run.regs.iter().enumerate() {
+            assert_eq!(v, history.timelines[r].last().map_or(0, |&(_, v)| v));
+        }
+    }
+
+    #[test]
+    fn intervals() {
+        assert_eq!(Some((0, 4)), restrict((0, 10), LT, 5));
+        assert_eq!(None, restrict((0, 10), GT, 10));
+        assert_eq!(Some((1, 10)), restrict((0, 10), NE, 0));
+        assert_eq!(None, restrict((3, 3), NE, 3));
+        assert_eq!(Some((3, 3)), restrict((0, 10), EQ, 3));
+        assert_eq!(Some((i64::MIN, i64::MIN)), restrict((i64::MIN, 0), LT, i64::MIN + 1));
+    }
+
+    #[test]
+    fn analysis() {
+        use self::Verdict::*;
+
+        let analysis = analyse(&parse_input(EXAMPLE));
+        assert_eq!(vec![Never, Always, Always, Always], analysis.verdicts);
+        assert_eq!(vec![("b", (0, 0)), ("a", (1, 1)), ("c", (-10, -10))], analysis.ranges);
+        assert_eq!(vec!["b"], analysis.always_zero);
+        // every register is tested somewhere, even where the outcome is fixed
+        assert_eq!(Vec::<&str>::new(), analysis.unconditioned);
+        assert_eq!(parse_input(&EXAMPLE[17..]), analysis.simplified);
+
+        let program = "\
+a inc 5 if x == 0
+b inc 1 if y != 0
+c inc 2 if b > 0
+c dec 2 if a < 5
+a inc 0 if c > 0
+d dec 3 if c >= 0
+d inc 1 if d > -3
+";
+        let analysis = analyse(&parse_input(program));
+        assert_eq!(vec![Always, Never, Never, Never, Never, Always, Never], analysis.verdicts);
+        assert_eq!(vec!["a", "d"], analysis.simplified.iter().map(|i| i.reg).collect::<Vec<_>>());
+        assert_eq!(vec!["x", "b", "y", "c"], analysis.always_zero);
+        assert_eq!("a inc 5 if x == 0\nd dec 3 if c >= 0",
+                   analysis.simplified.iter().map(|i| i.to_string())
+                       .collect::<Vec<_>>().join("\n"));
+
+        // a register that is its own condition
+        let analysis = analyse(&parse_input("a inc 7 if b == 0\nb inc 1 if a > 3\nb inc 1 if b < 1\nb dec 1 if b == 1\n"));
+        assert_eq!(vec![Always, Always, Never, Always], analysis.verdicts);
+        assert_eq!(("b", (0, 0)), analysis.ranges[1]);
+
+        let analysis = analyse(&parse_input("a inc 1 if b > 0\nc inc 2 if b < 0\nb inc 1 if c == 0\n"));
+        assert_eq!(vec!["a"], analysis.unconditioned);
+    }
+
+    #[test]
+    fn analysis_is_sound() {
+        for lines in 1..60 {
+            let program = bench_program(lines, 3)
+                .replace("00 ", " ").replace("00\n", "\n");
+            let instrs = parse_input(&program);
+            let analysis = analyse(&instrs);
+
+            let mut regs = HashMap::new();
+            for (i, v) in instrs.iter().zip(&analysis.verdicts) {
+                let creg: i64 = *regs.get(i.creg).unwrap_or(&0);
+                let fired = i.cop.holds(creg, i.carg);
+                assert!(*v != Verdict::Always || fired, "{}: {}", program, i);
+                assert!(*v != Verdict::Never || !fired, "{}: {}", program, i);
+                if fired {
+                    *regs.entry(i.reg).or_insert(0) += i.arg;
+                }
+            }
+            for &(reg, (lo, hi)) in &analysis.ranges {
+                let v = *regs.get(reg).unwrap_or(&0);
+                assert!(lo <= v && v <= hi);
+            }
+
+            let compiled = compile(&analysis.simplified);
+            let simple_regs = compiled.execute().unwrap().regs;
+            for (r, name) in compiled.names.iter().enumerate() {
+                assert_eq!(*regs.get(name).unwrap_or(&0), simple_regs[r]);
+            }
+            for reg in &analysis.always_zero {
+                assert_eq!(0, *regs.get(reg).unwrap_or(&0));
+            }
+        }
+    }
+
+    #[test]
+    fn language() {
+        use super::lang::*;
+
+        // the puzzle's programs are valid in the extended language
+        let prog: Program = EXAMPLE.parse().unwrap();
+        let mut m = Machine::new();
+        m.run(&prog, |_| panic!("nothing to print")).unwrap();
+        assert_eq!((Some(1), 10), (m.largest(), m.highest()));
+
+        let prog: Program = "\
+a inc 7
+b inc a * 2 - 1 if a > 5 && (c == 0 || c > 10)
+c dec b % 4 if !(a < b)   # not taken
+c dec a / 2 if b >= a
+print a, b + c, -c
+print
+".parse().unwrap();
+        let mut printed = String::new();
+        Machine::new().run(&prog, |text| printed += text).unwrap();
+        assert_eq!("7 10 3\na = 7\nb = 13\nc = -3\n", printed);
+
+        // registers can be named like keywords
+        let mut m = Machine::new();
+        m.exec(&"if inc 3 if print == 0".parse().unwrap()).unwrap();
+        assert_eq!(3, m.get("if"));
+
+        // and run them just as `solve` does
+        let mut programs = vec!["a inc 1 if b > 5\nb dec 1 if b == 0\n".to_string()];
+        programs.extend((1..40).map(|lines| bench_program(lines, 5)));
+        for program in &programs {
+            let prog: Program = program.parse().unwrap();
+            let mut m = Machine::new();
+            m.run(&prog, |_| {}).unwrap();
+            assert_eq!(solve(&run_program(program)), (m.largest().unwrap(), m.highest()), "{}", program);
+        }
+    }
+
+    #[test]
+    fn language_errors() {
+        use super::lang::*;
+
+        let err = "a inc 1\nb inc (2 if a > 0\n".parse::<Program>().unwrap_err();
+        assert_eq!("line 2, column 10: expected ')'", err.to_string());
+        let err = "a inc 1 if".parse::<Stmt>().unwrap_err();
+        assert_eq!("line 1, column 11: expected a value", err.to_string());
+        assert!("a add 1".parse::<Stmt>().is_err());
+        assert!("a inc 1 b".parse::<Stmt>().is_err());
+
+        let prog: Program = "a inc 1\n\nb inc 9223372036854775807\nb inc a\n".parse().unwrap();
+        let mut m = Machine::new();
+        let err = m.run(&prog, |_| {}).unwrap_err();
+        assert_eq!("line 4: arithmetic overflow", err.to_string());
+        assert_eq!(i64::MAX, m.get("b"));
+        assert_eq!(Err(EvalError::DivideByZero), m.exec(&"c inc 1 if 1 / c".parse().unwrap()));
+        // `&&` short-circuits
+        assert_eq!(Ok(Effect::Skipped), m.exec(&"c inc 1 if c != 0 && 1 / c".parse().unwrap()));
+    }
+
+    #[test]
+    fn repl_session() {
+        use super::lang::*;
+
+        let session = "a inc 5\nb dec 2 if a > 10\n\nb inc a +\nc inc 1 / b\nprint\n";
+        let mut out = Vec::new();
+        let mut m = Machine::new();
+        repl(&mut m, session.as_bytes(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\
+> a = 5
+> (condition false)
+> > parse error at column 10: expected a value
+> error: division by zero
+> a = 5
+> \n");
+    }
+
     #[test]
     fn real_input() {
         let input = include_str!("../inputs/day08.txt");
-        let x = solve(&input);
+        let x = solve(&run_program(input));
         assert_eq!(include_str!("../outputs/day08.txt"),
                    format!("{:?}", x));
     }
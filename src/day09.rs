@@ -1,43 +1,659 @@
-fn solve(stream: &str) -> (u32, u32) {
-    let mut depth = 0;
-    let mut in_garbage = false;
-    let mut skip_next = false;
-    let mut chars = stream.chars();
-    let mut score = 0;
-    let mut garbage_count = 0;
-
-    chars.next().unwrap(); // initial {
-    depth += 1;
-    while depth > 0 {
-        let c = chars.next().unwrap();
-        if !in_garbage {
-            match c {
-                '{' => depth += 1,
-                '}' => { score += depth; depth -= 1; },
-                '<' => in_garbage = true,
-                ',' => (),
-                _ => panic!("unexpected char: '{}'", c)
+use std::collections::{VecDeque, vec_deque};
+use std::fmt;
+use std::mem;
+use std::ops::Range;
+
+/// A group from the stream, with its byte span (braces included).
+/// Streams can nest far deeper than the call stack allows, so dropping,
+/// cloning, comparing and formatting groups all work without recursion.
+pub struct Group {
+    pub span: Range<usize>,
+    pub children: Vec<Group>,
+    /// The contents of each piece of garbage directly inside this group,
+    /// without the angle brackets or cancelled characters.
+    pub garbage: Vec<String>,
+    /// The byte span of each piece of garbage, angle brackets included.
+    pub garbage_spans: Vec<Range<usize>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub offset: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at byte {}: {}", self.offset, self.msg)
+    }
+}
+
+fn error<T>(offset: usize, msg: &str) -> Result<T, ParseError> {
+    Err(ParseError { offset, msg: msg.to_string() })
+}
+
+/// Parse a stream into its outermost group. Commas between items are
+/// optional, and whitespace may only follow the outermost group.
+pub fn parse(stream: &str) -> Result<Group, ParseError> {
+    let mut chars = stream.char_indices();
+    // the groups that are still open, innermost last
+    let mut open: Vec<Group> = Vec::new();
+    match chars.next() {
+        Some((0, '{')) => open.push(Group::new(0)),
+        _ => return error(0, "expected '{'"),
+    }
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '{' => open.push(Group::new(i)),
+            '}' => {
+                let mut group = open.pop().unwrap();
+                group.span.end = i + 1;
+                match open.last_mut() {
+                    Some(parent) => parent.children.push(group),
+                    None => {
+                        return match chars.find(|&(_, c)| !c.is_whitespace()) {
+                            Some((j, _)) => error(j, "unexpected input after the outermost group"),
+                            None => Ok(group),
+                        };
+                    },
+                }
+            },
+            '<' => {
+                let mut text = String::new();
+                let end = loop {
+                    match chars.next() {
+                        Some((_, '!')) => { chars.next(); },
+                        Some((j, '>')) => break j + 1,
+                        Some((_, c)) => text.push(c),
+                        None => return error(i, "unterminated garbage"),
+                    }
+                };
+                let group = open.last_mut().unwrap();
+                group.garbage.push(text);
+                group.garbage_spans.push(i..end);
+            },
+            ',' => {},
+            _ => return error(i, &format!("unexpected '{}'", c)),
+        }
+    }
+    error(open.last().unwrap().span.start, "unclosed group")
+}
+
+// Dropping children one level at a time would recurse as deep as the
+// stream is nested.
+impl Drop for Group {
+    fn drop(&mut self) {
+        let mut stack = mem::take(&mut self.children);
+        while let Some(mut g) = stack.pop() {
+            stack.append(&mut g.children);
+        }
+    }
+}
+
+impl Clone for Group {
+    fn clone(&self) -> Group {
+        let shell = |g: &Group| Group {
+            span: g.span.clone(),
+            children: Vec::with_capacity(g.children.len()),
+            garbage: g.garbage.clone(),
+            garbage_spans: g.garbage_spans.clone(),
+        };
+        // each original on the current path, with its copy so far
+        let mut stack = vec![(self, shell(self))];
+        loop {
+            let (orig, done) = {
+                let top = stack.last().unwrap();
+                (top.0, top.1.children.len())
+            };
+            match orig.children.get(done) {
+                Some(child) => stack.push((child, shell(child))),
+                None => {
+                    let (_, copy) = stack.pop().unwrap();
+                    match stack.last_mut() {
+                        Some(parent) => parent.1.children.push(copy),
+                        None => return copy,
+                    }
+                },
+            }
+        }
+    }
+}
+
+impl PartialEq for Group {
+    fn eq(&self, other: &Group) -> bool {
+        let mut stack = vec![(self, other)];
+        while let Some((a, b)) = stack.pop() {
+            if a.span != b.span || a.garbage != b.garbage || a.garbage_spans != b.garbage_spans
+                || a.children.len() != b.children.len() {
+                return false;
+            }
+            stack.extend(a.children.iter().zip(&b.children));
+        }
+        true
+    }
+}
+
+// The same text as a derived Debug, always on one line.
+impl fmt::Debug for Group {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        enum Step<'a> { Open(&'a Group), Text(&'static str), Close(&'a Group) }
+        let mut stack = vec![Step::Open(self)];
+        while let Some(step) = stack.pop() {
+            match step {
+                Step::Open(g) => {
+                    write!(f, "Group {{ span: {:?}, children: [", g.span)?;
+                    stack.push(Step::Close(g));
+                    for (i, child) in g.children.iter().enumerate().rev() {
+                        stack.push(Step::Open(child));
+                        if i > 0 {
+                            stack.push(Step::Text(", "));
+                        }
+                    }
+                },
+                Step::Text(text) => f.write_str(text)?,
+                Step::Close(g) => write!(f, "], garbage: {:?}, garbage_spans: {:?} }}",
+                                         g.garbage, g.garbage_spans)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Group {
+    fn new(start: usize) -> Group {
+        Group { span: start..start, children: Vec::new(), garbage: Vec::new(),
+                garbage_spans: Vec::new() }
+    }
+
+    // Every group in the tree with its depth, outermost (depth 1) first.
+    fn walk(&self) -> Vec<(usize, &Group)> {
+        let mut out = Vec::new();
+        let mut stack = vec![(1, self)];
+        while let Some((depth, g)) = stack.pop() {
+            out.push((depth, g));
+            stack.extend(g.children.iter().rev().map(|c| (depth + 1, c)));
+        }
+        out
+    }
+
+    /// How many groups there are at each depth, starting from 1.
+    pub fn depth_distribution(&self) -> Vec<usize> {
+        let mut counts = Vec::new();
+        for (depth, _) in self.walk() {
+            if counts.len() < depth {
+                counts.resize(depth, 0);
+            }
+            counts[depth - 1] += 1;
+        }
+        counts
+    }
+
+    pub fn score(&self) -> usize {
+        self.depth_distribution().iter().enumerate()
+            .map(|(d, n)| (d + 1) * n)
+            .sum()
+    }
+
+    /// Characters of garbage in the whole tree.
+    pub fn garbage_count(&self) -> usize {
+        self.walk().iter()
+            .flat_map(|&(_, g)| g.garbage.iter())
+            .map(|text| text.chars().count())
+            .sum()
+    }
+
+    /// The group with the most items (groups and garbage) directly
+    /// inside it, the first in the stream on a tie.
+    pub fn largest(&self) -> &Group {
+        let size = |g: &Group| g.children.len() + g.garbage.len();
+        self.walk().into_iter()
+            .map(|(_, g)| g)
+            .fold(self, |best, g| if size(g) > size(best) { g } else { best })
+    }
+
+    /// The group written back out in its original order, with cancelled
+    /// characters dropped and exactly one comma between items.
+    pub fn canonical(&self) -> String {
+        let mut out = String::new();
+        // groups still to close, and how far through their items we are
+        let mut stack = vec![(self, 0, 0)];
+        out.push('{');
+        while let Some(&mut (g, ref mut ci, ref mut gi)) = stack.last_mut() {
+            let next_child = g.children.get(*ci).map(|c| c.span.start);
+            let next_garbage = g.garbage_spans.get(*gi).map(|s| s.start);
+            if *ci + *gi > 0 && (next_child.is_some() || next_garbage.is_some()) {
+                out.push(',');
             }
+            match (next_child, next_garbage) {
+                (Some(c), Some(s)) if s < c => {
+                    out += &format!("<{}>", g.garbage[*gi]);
+                    *gi += 1;
+                },
+                (None, Some(_)) => {
+                    out += &format!("<{}>", g.garbage[*gi]);
+                    *gi += 1;
+                },
+                (Some(_), _) => {
+                    let child = &g.children[*ci];
+                    *ci += 1;
+                    out.push('{');
+                    stack.push((child, 0, 0));
+                },
+                (None, None) => {
+                    out.push('}');
+                    stack.pop();
+                },
+            }
+        }
+        out
+    }
+}
+
+/// Something a `StreamProcessor` found. Offsets are bytes from the start
+/// of the stream; the outermost group is at depth 1.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Open { offset: usize, depth: u64 },
+    Close { offset: usize, depth: u64 },
+    /// `text` leaves out the angle brackets and cancelled characters.
+    Garbage { span: Range<usize>, text: String },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StreamError {
+    /// The input ended before the outermost group was closed.
+    Incomplete,
+    /// The byte at `offset` can't appear there.
+    Malformed { offset: usize },
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StreamError::Incomplete => write!(f, "the stream ended inside a group"),
+            StreamError::Malformed { offset } => write!(f, "unexpected byte at offset {}", offset),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Problem {
+    /// The stream doesn't start with a group.
+    NoOpeningBrace,
+    /// A character that can't appear between groups.
+    Stray,
+    /// Input after the outermost group closed.
+    AfterEnd,
+    UnterminatedGarbage,
+    UnclosedGroup,
+}
+
+/// A change to the original stream. Offsets are into the original, and an
+/// insertion goes before the byte at its offset.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Repair {
+    Insert { offset: usize, text: &'static str },
+    Drop { span: Range<usize> },
+}
+
+/// A problem found in recovery mode, where it starts, and a repair.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub offset: usize,
+    pub problem: Problem,
+    pub repair: Repair,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let problem = match self.problem {
+            Problem::NoOpeningBrace => "the stream doesn't start with '{'",
+            Problem::Stray => "unexpected character",
+            Problem::AfterEnd => "input after the outermost group",
+            Problem::UnterminatedGarbage => "garbage is never closed",
+            Problem::UnclosedGroup => "group is never closed",
+        };
+        write!(f, "byte {}: {}; ", self.offset, problem)?;
+        match self.repair {
+            Repair::Insert { offset, text } => write!(f, "insert '{}' at byte {}", text, offset),
+            Repair::Drop { ref span } if span.len() == 1 => write!(f, "drop byte {}", span.start),
+            Repair::Drop { ref span } => write!(f, "drop bytes {}..{}", span.start, span.end),
+        }
+    }
+}
+
+/// The stream with the repairs made.
+pub fn apply_repairs(stream: &str, diagnostics: &[Diagnostic]) -> String {
+    let mut repairs: Vec<&Repair> = diagnostics.iter().map(|d| &d.repair).collect();
+    repairs.sort_by_key(|r| match **r {
+        Repair::Insert { offset, .. } => offset,
+        Repair::Drop { ref span } => span.start,
+    });
+    let bytes = stream.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+    for repair in repairs {
+        match *repair {
+            Repair::Insert { offset, text } => {
+                out.extend_from_slice(&bytes[pos..offset]);
+                out.extend_from_slice(text.as_bytes());
+                pos = offset;
+            },
+            Repair::Drop { ref span } => {
+                out.extend_from_slice(&bytes[pos..span.start]);
+                pos = span.end;
+            },
+        }
+    }
+    out.extend_from_slice(&bytes[pos..]);
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum State {
+    Start,
+    Groups,
+    Garbage,
+    // the byte after a '!'
+    Cancel,
+    Done,
+    Failed(usize),
+}
+
+/// Processes a stream fed in chunks of any size, keeping the score and
+/// garbage count up to date. While paused, fed bytes are held back until
+/// `resume`. Garbage is counted in characters, so a multi-byte character
+/// counts (and is cancelled) as one.
+///
+/// In recovery mode the processor never fails: each problem is recorded
+/// with a suggested repair and processing carries on as if the repair had
+/// been made.
+#[derive(Debug, Clone)]
+pub struct StreamProcessor {
+    state: State,
+    offset: usize,
+    depth: u64,
+    // where each open group started, outermost first
+    open_at: Vec<usize>,
+    recovering: bool,
+    diagnostics: Vec<Diagnostic>,
+    score: u64,
+    garbage_count: u64,
+    // set while skipping the rest of a cancelled multi-byte character
+    cancelling: bool,
+    garbage_start: usize,
+    garbage_text: Vec<u8>,
+    record: bool,
+    events: VecDeque<Event>,
+    paused: bool,
+    pending: Vec<u8>,
+}
+
+impl Default for StreamProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamProcessor {
+    pub fn new() -> Self {
+        StreamProcessor {
+            state: State::Start, offset: 0, depth: 0, open_at: Vec::new(),
+            recovering: false, diagnostics: Vec::new(), score: 0, garbage_count: 0,
+            cancelling: false, garbage_start: 0, garbage_text: Vec::new(),
+            record: true, events: VecDeque::new(), paused: false, pending: Vec::new(),
+        }
+    }
+
+    /// Whether to keep events for `events` (the default). Without them
+    /// only the totals are kept.
+    pub fn record_events(mut self, record: bool) -> Self {
+        self.record = record;
+        self
+    }
+
+    /// Whether to recover from problems instead of failing (off by
+    /// default).
+    pub fn recovering(mut self, recovering: bool) -> Self {
+        self.recovering = recovering;
+        self
+    }
+
+    /// The problems found so far in recovery mode.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Process the next chunk of the stream. Once the stream is malformed
+    /// every call returns the same error.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<(), StreamError> {
+        if self.paused {
+            self.pending.extend_from_slice(chunk);
         } else {
-            if skip_next {
-                skip_next = false;
-            } else {
-                match c {
-                    '>' => in_garbage = false,
-                    '!' => skip_next = true,
-                    _ => garbage_count += 1,
+            for &b in chunk {
+                if let State::Failed(_) = self.state {
+                    break;
+                }
+                self.step(b);
+            }
+        }
+        match self.state {
+            State::Failed(offset) => Err(StreamError::Malformed { offset }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Process anything fed while paused.
+    pub fn resume(&mut self) -> Result<(), StreamError> {
+        self.paused = false;
+        let pending = mem::take(&mut self.pending);
+        self.feed(&pending)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Take the events found so far.
+    pub fn events(&mut self) -> vec_deque::Drain<'_, Event> {
+        self.events.drain(..)
+    }
+
+    /// Bytes processed so far.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn score(&self) -> u64 {
+        self.score
+    }
+
+    pub fn garbage_count(&self) -> u64 {
+        self.garbage_count
+    }
+
+    /// The input has ended: the final score and garbage count, if the
+    /// stream was complete. Anything still paused is processed first. In
+    /// recovery mode, whatever is still open is closed.
+    pub fn finish(&mut self) -> Result<(u64, u64), StreamError> {
+        self.resume()?;
+        if self.recovering {
+            self.close_all();
+        }
+        match self.state {
+            State::Done => Ok((self.score, self.garbage_count)),
+            State::Failed(offset) => Err(StreamError::Malformed { offset }),
+            _ => Err(StreamError::Incomplete),
+        }
+    }
+
+    fn diagnose(&mut self, offset: usize, problem: Problem, repair: Repair) {
+        self.diagnostics.push(Diagnostic { offset, problem, repair });
+    }
+
+    // Drop a byte, as part of the previous drop if the byte continues a
+    // dropped character. Everything after the end is one drop.
+    fn drop_byte(&mut self, offset: usize, problem: Problem, continuing: bool) {
+        if let Some(last) = self.diagnostics.last_mut() {
+            if let Repair::Drop { ref mut span } = last.repair {
+                let after_end = problem == Problem::AfterEnd && last.problem == problem;
+                if after_end || (continuing && span.end == offset) {
+                    span.end = offset + 1;
+                    return;
                 }
             }
         }
+        self.diagnose(offset, problem, Repair::Drop { span: offset..offset + 1 });
+    }
+
+    fn close_all(&mut self) {
+        let end = self.offset;
+        match self.state {
+            State::Start => {
+                self.diagnose(end, Problem::NoOpeningBrace, Repair::Insert { offset: end, text: "{}" });
+                self.score += 1;
+                self.state = State::Done;
+                self.emit(Event::Open { offset: end, depth: 1 });
+                self.emit(Event::Close { offset: end, depth: 1 });
+            },
+            State::Garbage | State::Cancel => {
+                // after a '!', the first '>' is cancelled
+                let text = if self.state == State::Cancel { ">>" } else { ">" };
+                let start = self.garbage_start;
+                self.diagnose(start, Problem::UnterminatedGarbage, Repair::Insert { offset: end, text });
+                for _ in 0..text.len() {
+                    self.step_byte(b'>', end);
+                }
+            },
+            _ => {},
+        }
+        while self.state == State::Groups {
+            let start = *self.open_at.last().unwrap();
+            self.diagnose(start, Problem::UnclosedGroup, Repair::Insert { offset: end, text: "}" });
+            self.step_byte(b'}', end);
+        }
+    }
+
+    fn recover(&mut self, b: u8, offset: usize) {
+        let continuing = b & 0xC0 == 0x80;
+        match self.state {
+            State::Start if b == b'<' || b == b',' || b == b'}' => {
+                self.diagnose(offset, Problem::NoOpeningBrace, Repair::Insert { offset, text: "{" });
+                self.step_byte(b'{', offset);
+                self.step_byte(b, offset);
+            },
+            State::Start | State::Groups => self.drop_byte(offset, Problem::Stray, continuing),
+            _ => self.drop_byte(offset, Problem::AfterEnd, continuing),
+        }
+    }
+
+    fn emit(&mut self, event: Event) {
+        if self.record {
+            self.events.push_back(event);
+        }
+    }
+
+    fn step(&mut self, b: u8) {
+        let offset = self.offset;
+        self.offset += 1;
+        self.step_byte(b, offset);
     }
 
-    (score, garbage_count)
+    fn step_byte(&mut self, b: u8, offset: usize) {
+        // continuation bytes of a UTF-8 character
+        let continuing = b & 0xC0 == 0x80;
+        match (self.state, b) {
+            (State::Start, b'{') | (State::Groups, b'{') => {
+                self.depth += 1;
+                self.open_at.push(offset);
+                self.state = State::Groups;
+                let depth = self.depth;
+                self.emit(Event::Open { offset, depth });
+            },
+            (State::Groups, b'}') => {
+                let depth = self.depth;
+                self.score += depth;
+                self.depth -= 1;
+                self.open_at.pop();
+                if self.depth == 0 {
+                    self.state = State::Done;
+                }
+                self.emit(Event::Close { offset, depth });
+            },
+            (State::Groups, b'<') => {
+                self.state = State::Garbage;
+                self.garbage_start = offset;
+                self.cancelling = false;
+            },
+            (State::Groups, b',') => {},
+            (State::Garbage, _) if continuing => {
+                if !self.cancelling && self.record {
+                    self.garbage_text.push(b);
+                }
+            },
+            (State::Garbage, b'>') => {
+                self.state = State::Groups;
+                let text = String::from_utf8_lossy(&self.garbage_text).into_owned();
+                self.garbage_text.clear();
+                let span = self.garbage_start..offset + 1;
+                self.emit(Event::Garbage { span, text });
+            },
+            (State::Garbage, b'!') => self.state = State::Cancel,
+            (State::Garbage, _) => {
+                self.cancelling = false;
+                self.garbage_count += 1;
+                if self.record {
+                    self.garbage_text.push(b);
+                }
+            },
+            (State::Cancel, _) => {
+                self.state = State::Garbage;
+                self.cancelling = true;
+            },
+            (State::Done, _) if b.is_ascii_whitespace() => {},
+            _ if self.recovering => self.recover(b, offset),
+            _ => self.state = State::Failed(offset),
+        }
+    }
+}
+
+/// The total score and garbage count of a well-formed stream.
+pub fn solve(stream: &str) -> Result<(u64, u64), StreamError> {
+    let mut processor = StreamProcessor::new().record_events(false);
+    processor.feed(stream.as_bytes())?;
+    processor.finish()
+}
+
+/// Best-effort totals for a stream that may be malformed, with what was
+/// wrong with it.
+pub fn recover(stream: &str) -> ((u64, u64), Vec<Diagnostic>) {
+    let mut processor = StreamProcessor::new().record_events(false).recovering(true);
+    let totals = processor.feed(stream.as_bytes())
+        .and_then(|_| processor.finish())
+        .expect("recovery mode doesn't fail");
+    (totals, processor.diagnostics)
 }
 
 pub fn run(input: &str) {
-    let (part1, part2) = solve(input);
+    let (part1, part2) = solve(input).unwrap_or_else(|e| {
+        let (totals, diagnostics) = recover(input);
+        println!("the stream is malformed ({}); the answers are for the repaired stream:", e);
+        for d in &diagnostics {
+            println!("  {}", d);
+        }
+        totals
+    });
     println!("the solution to part 1 is {}", part1);
     println!("the solution to part 2 is {}", part2);
+    if let Ok(root) = parse(input) {
+        let depths = root.depth_distribution();
+        println!("{} groups, up to {} deep; the one with the most items spans bytes {:?}",
+                 depths.iter().sum::<usize>(), depths.len(), root.largest().span);
+    }
 }
 
 #[cfg(test)]
@@ -46,31 +662,223 @@ mod tests {
 
     #[test]
     fn example1() {
-        assert_eq!(solve("{}").0, 1);
-        assert_eq!(solve("{{{}}}").0, 6);
-        assert_eq!(solve("{{},{}}").0, 5);
-        assert_eq!(solve("{{{},{},{{}}}}").0, 16);
-        assert_eq!(solve("{<a>,<a>,<a>,<a>}").0, 1);
-        assert_eq!(solve("{{<ab>},{<ab>},{<ab>},{<ab>}}").0, 9);
-        assert_eq!(solve("{{<!!>},{<!!>},{<!!>},{<!!>}}").0, 9);
-        assert_eq!(solve("{{<a!>},{<a!>},{<a!>},{<ab>}}").0, 3);
+        assert_eq!(solve("{}").unwrap().0, 1);
+        assert_eq!(solve("{{{}}}").unwrap().0, 6);
+        assert_eq!(solve("{{},{}}").unwrap().0, 5);
+        assert_eq!(solve("{{{},{},{{}}}}").unwrap().0, 16);
+        assert_eq!(solve("{<a>,<a>,<a>,<a>}").unwrap().0, 1);
+        assert_eq!(solve("{{<ab>},{<ab>},{<ab>},{<ab>}}").unwrap().0, 9);
+        assert_eq!(solve("{{<!!>},{<!!>},{<!!>},{<!!>}}").unwrap().0, 9);
+        assert_eq!(solve("{{<a!>},{<a!>},{<a!>},{<ab>}}").unwrap().0, 3);
     }
 
     #[test]
     fn example2() {
-        assert_eq!(solve("{<>}").1, 0);
-        assert_eq!(solve("{<random characters>}").1, 17);
-        assert_eq!(solve("{<<<<>}").1, 3);
-        assert_eq!(solve("{<{!>}>}").1, 2);
-        assert_eq!(solve("{<!!>}").1, 0);
-        assert_eq!(solve("{<!!!>>}").1, 0);
-        assert_eq!(solve("{<{o\"i!a,<{i<a>}").1, 10);
+        assert_eq!(solve("{<>}").unwrap().1, 0);
+        assert_eq!(solve("{<random characters>}").unwrap().1, 17);
+        assert_eq!(solve("{<<<<>}").unwrap().1, 3);
+        assert_eq!(solve("{<{!>}>}").unwrap().1, 2);
+        assert_eq!(solve("{<!!>}").unwrap().1, 0);
+        assert_eq!(solve("{<!!!>>}").unwrap().1, 0);
+        assert_eq!(solve("{<{o\"i!a,<{i<a>}").unwrap().1, 10);
+    }
+
+    #[test]
+    fn tree() {
+        let root = parse("{{},<x>,{<a!>b>,{},{},{}}}\n").unwrap();
+        assert_eq!(0..26, root.span);
+        assert_eq!(vec![1..3, 8..25], root.children.iter().map(|g| g.span.clone()).collect::<Vec<_>>());
+        assert_eq!(vec!["x"], root.garbage);
+        assert_eq!(vec![4..7], root.garbage_spans);
+        assert_eq!(vec!["ab"], root.children[1].garbage);
+        assert_eq!(vec![9..15], root.children[1].garbage_spans);
+        assert_eq!(vec![1, 2, 3], root.depth_distribution());
+        assert_eq!(8..25, root.largest().span);
+        assert_eq!("{{},<x>,{<ab>,{},{},{}}}", root.canonical());
+
+        let examples = [
+            "{}", "{{{}}}", "{{},{}}", "{{{},{},{{}}}}", "{<a>,<a>,<a>,<a>}",
+            "{{<ab>},{<ab>},{<ab>},{<ab>}}", "{{<!!>},{<!!>},{<!!>},{<!!>}}",
+            "{{<a!>},{<a!>},{<a!>},{<ab>}}", "{<{o\"i!a,<{i<a>}", "{<!!!>>}",
+        ];
+        for stream in examples.iter() {
+            let root = parse(stream).unwrap();
+            let (score, garbage) = solve(stream).unwrap();
+            assert_eq!((score as usize, garbage as usize), (root.score(), root.garbage_count()));
+            let again = parse(&root.canonical()).unwrap();
+            assert_eq!((root.score(), root.garbage_count()), (again.score(), again.garbage_count()));
+            assert_eq!(root.canonical(), again.canonical());
+        }
+        assert_eq!("{{<a},{<a},{<a},{<ab>}}", parse("{{<a!>},{<a!>},{<a!>},{<ab>}}").unwrap().canonical());
+        assert_eq!("{<>,<>}", parse("{<>,,<!>>}").unwrap().canonical());
+
+        // deep nesting doesn't use the call stack
+        let deep = "{".repeat(100_000) + &"}".repeat(100_000);
+        let root = parse(&deep).unwrap();
+        assert_eq!(100_000, root.depth_distribution().len());
+        assert_eq!(deep, root.canonical());
+        let copy = root.clone();
+        assert!(copy == root);
+        let text = format!("{:?}", copy);
+        assert_eq!(100_000, text.matches("Group { span: ").count());
+        assert!(text.contains("[Group { span: 99999..100001, children: [], \
+                               garbage: [], garbage_spans: [] }], garbage: []"));
+    }
+
+    #[test]
+    fn group_traits() {
+        let root = parse("{{<a>},{}}").unwrap();
+        assert_eq!(format!("{:?}", root), "Group { span: 0..10, children: [\
+                    Group { span: 1..6, children: [], garbage: [\"a\"], garbage_spans: [2..5] }, \
+                    Group { span: 7..9, children: [], garbage: [], garbage_spans: [] }], \
+                    garbage: [], garbage_spans: [] }");
+        let mut copy = root.clone();
+        assert_eq!(root, copy);
+        copy.children[1].span.end += 1;
+        assert!(root != copy);
+        assert!(root != parse("{{<b>},{}}").unwrap());
+        assert!(root != parse("{{<a>}}").unwrap());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = |s: &str| parse(s).unwrap_err().to_string();
+        assert_eq!("at byte 0: expected '{'", err("<>"));
+        assert_eq!("at byte 2: unexpected 'x'", err("{{x}}"));
+        assert_eq!("at byte 1: unterminated garbage", err("{<abc!>"));
+        assert_eq!("at byte 0: unclosed group", err("{{}"));
+        assert_eq!("at byte 1: unclosed group", err("{{{}"));
+        assert_eq!("at byte 3: unexpected input after the outermost group", err("{} {}"));
+    }
+
+    #[test]
+    fn stream_events() {
+        let stream = "{{<a!>b>},{}}";
+        let mut p = StreamProcessor::new();
+        p.feed(stream.as_bytes()).unwrap();
+        assert_eq!(p.events().collect::<Vec<_>>(), vec![
+            Event::Open { offset: 0, depth: 1 },
+            Event::Open { offset: 1, depth: 2 },
+            Event::Garbage { span: 2..8, text: "ab".to_string() },
+            Event::Close { offset: 8, depth: 2 },
+            Event::Open { offset: 10, depth: 2 },
+            Event::Close { offset: 11, depth: 2 },
+            Event::Close { offset: 12, depth: 1 },
+        ]);
+        assert_eq!(0, p.events().count());
+        assert_eq!(Ok((5, 2)), p.finish());
+
+        // the same events and totals however the stream is split up
+        let stream = "{{<ab>},{<!!é!é>},{<a!>},{<{o\"i!a,<{i<a>},{{},{<{!>}>}}}\n";
+        let mut whole = StreamProcessor::new();
+        whole.feed(stream.as_bytes()).unwrap();
+        let expected: Vec<Event> = whole.events().collect();
+        let totals = whole.finish().unwrap();
+        assert_eq!((1 + 4 * 2 + 3 + 3, 2 + 1 + 15 + 2), totals);
+        for size in 1..8 {
+            let mut p = StreamProcessor::new();
+            let mut events = Vec::new();
+            for chunk in stream.as_bytes().chunks(size) {
+                p.feed(chunk).unwrap();
+                events.extend(p.events());
+            }
+            assert_eq!(expected, events);
+            assert_eq!(Ok(totals), p.finish());
+        }
+    }
+
+    #[test]
+    fn stream_pause() {
+        let mut p = StreamProcessor::new();
+        p.feed(b"{{<ab").unwrap();
+        p.pause();
+        p.feed(b"c>}").unwrap();
+        assert!(p.is_paused());
+        assert_eq!((5, 0, 2), (p.offset(), p.score(), p.garbage_count()));
+        p.resume().unwrap();
+        assert_eq!((8, 2, 3), (p.offset(), p.score(), p.garbage_count()));
+        p.pause();
+        p.feed(b"}").unwrap();
+        assert_eq!(Ok((3, 3)), p.finish());
+    }
+
+    #[test]
+    fn stream_errors() {
+        let finish = |s: &str| {
+            let mut p = StreamProcessor::new().record_events(false);
+            let _ = p.feed(s.as_bytes());
+            p.finish()
+        };
+        assert_eq!(Err(StreamError::Incomplete), finish(""));
+        assert_eq!(Err(StreamError::Incomplete), finish("{{}"));
+        assert_eq!(Err(StreamError::Incomplete), finish("{<!"));
+        assert_eq!(Err(StreamError::Malformed { offset: 0 }), finish(" {}"));
+        assert_eq!(Err(StreamError::Malformed { offset: 2 }), finish("{{x}}"));
+        assert_eq!(Err(StreamError::Malformed { offset: 3 }), finish("{} {}"));
+        assert_eq!(Ok((1, 0)), finish("{}\r\n"));
+        assert_eq!(Err(StreamError::Malformed { offset: 2 }), solve("{{x}}"));
+        assert_eq!(Err(StreamError::Incomplete), solve("{<!"));
+
+        let mut p = StreamProcessor::new();
+        assert_eq!(Ok(()), p.feed(b"{{"));
+        assert_eq!(Err(StreamError::Malformed { offset: 3 }), p.feed(b"}]}"));
+        assert_eq!(Err(StreamError::Malformed { offset: 3 }), p.feed(b"}"));
+        assert_eq!(4, p.offset());
+    }
+
+    #[test]
+    fn recovery() {
+        let (totals, diagnostics) = recover("{{<ab>},{<a!>}}");
+        // the garbage swallows the closing braces
+        assert_eq!((1 + 2 + 2, 2 + 3), totals);
+        assert_eq!(diagnostics, vec![
+            Diagnostic { offset: 9, problem: Problem::UnterminatedGarbage,
+                         repair: Repair::Insert { offset: 15, text: ">" } },
+            Diagnostic { offset: 8, problem: Problem::UnclosedGroup,
+                         repair: Repair::Insert { offset: 15, text: "}" } },
+            Diagnostic { offset: 0, problem: Problem::UnclosedGroup,
+                         repair: Repair::Insert { offset: 15, text: "}" } },
+        ]);
+        assert_eq!("byte 9: garbage is never closed; insert '>' at byte 15",
+                   diagnostics[0].to_string());
+
+        let (totals, diagnostics) = recover("{{x},é{}}}} {}\n");
+        assert_eq!((5, 0), totals);
+        assert_eq!(diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>(), vec![
+            "byte 2: unexpected character; drop byte 2",
+            "byte 5: unexpected character; drop bytes 5..7",
+            "byte 10: input after the outermost group; drop bytes 10..15",
+        ]);
+
+        assert_eq!(((3, 1), 1), {
+            let (t, d) = recover("<a>,{}}");
+            (t, d.len())
+        });
+        assert_eq!(recover("").1[0].repair, Repair::Insert { offset: 0, text: "{}" });
+        assert_eq!(recover("{<!").1[0].repair, Repair::Insert { offset: 3, text: ">>" });
+        assert_eq!(((10, 3), vec![]), recover("{{<ab>},{{<!>a>}},{<!!>}}"));
+
+        // making the repairs gives a valid stream with the same totals
+        let broken = [
+            "", "x", "}", "{", "{{}", "{<", "{<!", "{<a!>}", "{{x},é{}}}} {}",
+            "<a>,{}}", "{{<ab>},{<a!>}}", "a{b{c}d}e", "{{{},{<!>},{{}}}}}",
+        ];
+        for stream in broken.iter() {
+            assert!(solve(stream).is_err(), "{}", stream);
+            let (totals, diagnostics) = recover(stream);
+            assert!(!diagnostics.is_empty(), "{}", stream);
+            let repaired = apply_repairs(stream, &diagnostics);
+            let mut strict = StreamProcessor::new();
+            strict.feed(repaired.as_bytes()).unwrap();
+            assert_eq!(Ok(totals), strict.finish(), "{} -> {}", stream, repaired);
+            assert_eq!((totals, vec![]), recover(&repaired));
+        }
     }
 
     #[test]
     fn real_input() {
         let input = include_str!("../inputs/day09.txt");
-        let x = solve(&input);
+        let x = solve(&input).unwrap();
         assert_eq!(include_str!("../outputs/day09.txt"),
                    format!("{:?}", x));
     }
@@ -1,8 +1,13 @@
 use std::fmt::{Display, Formatter, Result};
+use std::hash::{BuildHasher, Hasher};
 use std::ops::Deref;
+use std::str::FromStr;
+use std::thread;
 use std::time::Instant;
 
-pub struct Digest([u8; 16]);
+/// A knot hash, `KnotParams::digest_width` bytes wide (16 for the puzzle).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Digest(Vec<u8>);
 
 impl Display for Digest {
     fn fmt(&self, f: &mut Formatter) -> Result {
@@ -14,31 +19,249 @@ impl Display for Digest {
 }
 
 impl Deref for Digest {
-    type Target = [u8; 16];
+    type Target = [u8];
     fn deref(&self) -> &Self::Target { &self.0 }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseDigestError {
+    Empty,
+    OddLength,
+    BadDigit(usize),
+}
+
+impl Display for ParseDigestError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match *self {
+            ParseDigestError::Empty => write!(f, "empty digest"),
+            ParseDigestError::OddLength => write!(f, "odd number of hex digits"),
+            ParseDigestError::BadDigit(i) => write!(f, "bad hex digit at position {}", i + 1),
+        }
+    }
+}
+
+impl FromStr for Digest {
+    type Err = ParseDigestError;
+
+    /// Parses hex, as written by `Display`, in either case.
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(ParseDigestError::Empty);
+        }
+        if !s.len().is_multiple_of(2) {
+            return Err(ParseDigestError::OddLength);
+        }
+        let digit = |i: usize| (s.as_bytes()[i] as char).to_digit(16)
+            .map(|d| d as u8)
+            .ok_or(ParseDigestError::BadDigit(i));
+        (0..s.len()).step_by(2)
+            .map(|i| Ok(digit(i)? << 4 | digit(i + 1)?))
+            .collect::<::std::result::Result<Vec<u8>, _>>()
+            .map(Digest)
+    }
+}
+
+/// What to vary in the knot hash. The default is the puzzle's: a list of
+/// 256, 64 rounds, lengths `[17, 31, 73, 47, 23]` after the key, and a
+/// 16-byte digest.
+///
+/// The list must hold at least 256 elements, so every key byte is a valid
+/// length, and at most 65536. The digest XORs equal blocks of the list,
+/// one byte per block, keeping the low 8 bits when elements are larger.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KnotParams {
+    list_size: usize,
+    rounds: u32,
+    suffix: Vec<u8>,
+    digest_width: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamError {
+    ListSize(usize),
+    /// The digest width must divide the list size.
+    DigestWidth { width: usize, list_size: usize },
+}
+
+impl Display for ParamError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match *self {
+            ParamError::ListSize(n) =>
+                write!(f, "list size {} is not between 256 and 65536", n),
+            ParamError::DigestWidth { width, list_size } =>
+                write!(f, "a {}-byte digest doesn't divide a list of {}", width, list_size),
+        }
+    }
+}
+
+impl Default for KnotParams {
+    fn default() -> Self {
+        KnotParams { list_size: 256, rounds: 64, suffix: vec![17, 31, 73, 47, 23], digest_width: 16 }
+    }
+}
+
+impl KnotParams {
+    pub fn list_size(mut self, n: usize) -> Self {
+        self.list_size = n;
+        self
+    }
+
+    pub fn rounds(mut self, n: u32) -> Self {
+        self.rounds = n;
+        self
+    }
+
+    pub fn suffix(mut self, lengths: &[u8]) -> Self {
+        self.suffix = lengths.to_vec();
+        self
+    }
+
+    /// In bytes.
+    pub fn digest_width(mut self, n: usize) -> Self {
+        self.digest_width = n;
+        self
+    }
+
+    pub fn validate(&self) -> ::std::result::Result<(), ParamError> {
+        if self.list_size < 256 || self.list_size > 65536 {
+            Err(ParamError::ListSize(self.list_size))
+        } else if self.digest_width == 0 || !self.list_size.is_multiple_of(self.digest_width) {
+            Err(ParamError::DigestWidth { width: self.digest_width, list_size: self.list_size })
+        } else {
+            Ok(())
+        }
+    }
+}
+
 pub fn knot_hash(text: &str) -> Digest {
-    let lengths = prep_key(text);
-    let mut elems: Vec<u8> = (0..256).map(|x| x as u8).collect();
-    apply_lengths(&mut elems, &lengths, 64);
-    let mut hash = Digest([0;16]);
-    elems.chunks(16)
-        .map(|ch| ch.iter().fold(0, |acc, &x| acc ^ x))
-        .enumerate()
-        .for_each(|(i,b)| hash.0[i] = b);
-    hash
+    let mut hasher = KnotHasher::new();
+    hasher.update(text.as_bytes());
+    hasher.finalize()
+}
+
+/// Knot hashes arbitrary bytes fed in pieces. Every round of the hash
+/// goes over the whole input, so it is kept until `finalize`.
+///
+/// As a `Hasher` (for `HashMap`s, with `BuildKnotHasher`) it is slow, and
+/// `finish` gives the first 8 bytes of the digest.
+#[derive(Debug, Clone, Default)]
+pub struct KnotHasher {
+    params: KnotParams,
+    data: Vec<u8>,
+}
+
+impl KnotHasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_params(params: KnotParams) -> ::std::result::Result<Self, ParamError> {
+        params.validate()?;
+        Ok(KnotHasher { params, data: Vec::new() })
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn finalize(self) -> Digest {
+        self.digest()
+    }
+
+    fn digest(&self) -> Digest {
+        Workspace::default().hash(&self.params, &self.data)
+    }
+}
+
+// Buffers for hashing, kept to be reused from one key to the next.
+#[derive(Default)]
+struct Workspace {
+    lengths: Vec<usize>,
+    small: Vec<u8>,
+    wide: Vec<u16>,
+}
+
+impl Workspace {
+    fn hash(&mut self, p: &KnotParams, key: &[u8]) -> Digest {
+        prep_key(key, &p.suffix, &mut self.lengths);
+        if p.list_size == 256 {
+            self.small.clear();
+            self.small.extend(0..=255);
+            apply_lengths(&mut self.small, &self.lengths, p.rounds);
+            dense_hash(&self.small, p.digest_width, |x| x)
+        } else {
+            self.wide.clear();
+            self.wide.extend((0..p.list_size).map(|x| x as u16));
+            apply_lengths(&mut self.wide, &self.lengths, p.rounds);
+            dense_hash(&self.wide, p.digest_width, |x| x as u8)
+        }
+    }
+}
+
+/// The knot hashes of many keys, in order, shared out over the available
+/// cores.
+pub fn knot_hash_many(keys: &[&str]) -> Vec<Digest> {
+    // below this many keys per thread, starting threads costs more
+    const MIN_CHUNK: usize = 16;
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = keys.len().div_ceil(threads).max(MIN_CHUNK);
+    let params = KnotParams::default();
+    let hash_chunk = |keys: &[&str]| {
+        let mut ws = Workspace::default();
+        keys.iter().map(|k| ws.hash(&params, k.as_bytes())).collect::<Vec<_>>()
+    };
+    if keys.len() <= chunk {
+        return hash_chunk(keys);
+    }
+    thread::scope(|scope| {
+        let handles: Vec<_> = keys.chunks(chunk)
+            .map(|part| scope.spawn(move || hash_chunk(part)))
+            .collect();
+        handles.into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect()
+    })
 }
 
-fn prep_key(keystr: &str) -> Vec<usize> {
-    const SUGAR: [usize; 5] = [17, 31, 73, 47, 23];
-    let mut key = Vec::with_capacity(keystr.len() + SUGAR.len());
-    keystr.bytes().for_each(|b| key.push(b as usize));
-    key.extend(&SUGAR);
-    key
+impl Hasher for KnotHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.update(bytes);
+    }
+
+    // The first eight digest bytes, big-endian. A narrower digest fills
+    // only the low bytes.
+    fn finish(&self) -> u64 {
+        self.digest().iter().take(8).fold(0, |acc, &b| acc << 8 | b as u64)
+    }
 }
 
-fn apply_lengths(elems: &mut [u8], lengths: &[usize], rounds: u32) {
+/// Builds `KnotHasher`s, e.g. for `HashMap::with_hasher`.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct BuildKnotHasher;
+
+impl BuildHasher for BuildKnotHasher {
+    type Hasher = KnotHasher;
+
+    fn build_hasher(&self) -> KnotHasher {
+        KnotHasher::new()
+    }
+}
+
+fn dense_hash<T, F>(elems: &[T], width: usize, byte: F) -> Digest where
+    T: Copy + ::std::ops::BitXor<Output = T>,
+    F: Fn(T) -> u8,
+{
+    Digest(elems.chunks(elems.len() / width)
+        .map(|ch| byte(ch[1..].iter().fold(ch[0], |acc, &x| acc ^ x)))
+        .collect())
+}
+
+fn prep_key(key: &[u8], suffix: &[u8], lengths: &mut Vec<usize>) {
+    lengths.clear();
+    lengths.extend(key.iter().chain(suffix).map(|&b| b as usize));
+}
+
+fn apply_lengths<T: Copy>(elems: &mut [T], lengths: &[usize], rounds: u32) {
     let mut skip = 0;
     let mut offset = 0;
     for _ in 0..rounds {
@@ -50,7 +273,7 @@ fn apply_lengths(elems: &mut [u8], lengths: &[usize], rounds: u32) {
     }
 }
 
-fn twist(xs: &mut [u8], len: usize, offset: usize) {
+fn twist<T: Copy>(xs: &mut [T], len: usize, offset: usize) {
     if len == 0 { return; }
 
     let size = xs.len();
@@ -125,6 +348,21 @@ pub fn run(input: &str) {
     println!("{} hashes in {}.{:03}s", REPS, e.as_secs(), e.subsec_nanos() / 1000000);
     let hps = REPS as f64 / (e.as_secs() as f64 + e.subsec_nanos() as f64 * 1e-9);
     println!("{:.0} hashes/sec", hps);
+
+    const KEYS: usize = 20_000;
+    let keys: Vec<String> = (0..KEYS).map(|i| format!("AoC 2017-{}", i)).collect();
+    let keys: Vec<&str> = keys.iter().map(|k| k.as_str()).collect();
+    let now = Instant::now();
+    for k in &keys {
+        knot_hash(k);
+    }
+    let serial = now.elapsed();
+    let now = Instant::now();
+    knot_hash_many(&keys);
+    let parallel = now.elapsed();
+    let rate = |e: ::std::time::Duration| KEYS as f64 / e.as_secs_f64();
+    println!("{} different keys: {:.0} hashes/sec one at a time, {:.0} hashes/sec with knot_hash_many",
+             KEYS, rate(serial), rate(parallel));
 }
 
 #[cfg(test)]
@@ -141,7 +379,9 @@ mod tests {
 
     #[test]
     fn keyprep() {
-        assert_eq!(prep_key("1,2,3"), vec![49,44,50,44,51,17,31,73,47,23]);
+        let mut lengths = vec![1, 2];
+        prep_key(b"1,2,3", &KnotParams::default().suffix, &mut lengths);
+        assert_eq!(lengths, vec![49,44,50,44,51,17,31,73,47,23]);
     }
 
     #[test]
@@ -152,6 +392,109 @@ mod tests {
         assert_eq!(knot_hash("1,2,4").to_string(), "63960835bcdc130f0b66d7ff4f6a5a8e");
     }
 
+    #[test]
+    fn hasher() {
+        use std::collections::HashMap;
+
+        for &key in &["", "AoC 2017", "1,2,3", "1,2,4"] {
+            let mut hasher = KnotHasher::new();
+            for piece in key.as_bytes().chunks(3) {
+                hasher.update(piece);
+            }
+            assert_eq!(knot_hash(key), hasher.clone().finalize());
+
+            let expected = knot_hash(key)[..8].iter().fold(0, |acc, &b| acc << 8 | b as u64);
+            assert_eq!(expected, hasher.finish());
+        }
+
+        let mut map = HashMap::with_hasher(BuildKnotHasher);
+        for i in 0..100 {
+            map.insert(format!("flqrgnkx-{}", i), i);
+        }
+        assert_eq!(Some(&42), map.get("flqrgnkx-42"));
+        assert_eq!(100, map.len());
+
+        // digests narrower than a u64 don't panic
+        for width in [1, 2, 4] {
+            let params = KnotParams::default().digest_width(width);
+            let mut hasher = KnotHasher::with_params(params).unwrap();
+            hasher.write(b"AoC 2017");
+            let digest = hasher.clone().finalize();
+            let expected = digest.iter().fold(0, |acc, &b| acc << 8 | b as u64);
+            assert_eq!(expected, hasher.finish());
+            assert!(hasher.finish() < 1 << (8 * width));
+        }
+    }
+
+    #[test]
+    fn params() {
+        let hash = |params: KnotParams, key: &str| {
+            let mut hasher = KnotHasher::with_params(params).unwrap();
+            hasher.update(key.as_bytes());
+            hasher.finalize()
+        };
+        assert_eq!(knot_hash("AoC 2017"), hash(KnotParams::default(), "AoC 2017"));
+        assert_eq!(knot_hash("AoC 2017"),
+                   hash(KnotParams::default().rounds(64).suffix(&[17, 31, 73, 47, 23]), "AoC 2017"));
+
+        // one round with no suffix and a digest of the whole list is the
+        // list after part 1's single round
+        let mut elems: Vec<u8> = (0..=255).collect();
+        apply_lengths(&mut elems, &[49, 44, 50], 1);
+        assert_eq!(elems, *hash(KnotParams::default().rounds(1).suffix(&[]).digest_width(256), "1,2"));
+
+        let wide = hash(KnotParams::default().list_size(1024).digest_width(128), "AoC 2017");
+        assert_eq!(128, wide.len());
+        assert_eq!(256, wide.to_string().len());
+        assert_ne!(wide, hash(KnotParams::default().list_size(1024).digest_width(128), "AoC 2018"));
+        let coarse = hash(KnotParams::default().digest_width(4), "AoC 2017");
+        let fine = knot_hash("AoC 2017");
+        for i in 0..4 {
+            assert_eq!(coarse[i], fine[4 * i..4 * i + 4].iter().fold(0, |a, &b| a ^ b));
+        }
+
+        assert_eq!(Err(ParamError::ListSize(5)), KnotParams::default().list_size(5).validate());
+        assert_eq!(Err(ParamError::ListSize(70000)), KnotParams::default().list_size(70000).validate());
+        assert_eq!(Err(ParamError::DigestWidth { width: 3, list_size: 256 }),
+                   KnotParams::default().digest_width(3).validate());
+        assert!(KnotHasher::with_params(KnotParams::default().digest_width(0)).is_err());
+    }
+
+    #[test]
+    fn digest_hex() {
+        for &key in &["", "AoC 2017", "1,2,3"] {
+            let digest = knot_hash(key);
+            assert_eq!(Ok(digest.clone()), digest.to_string().parse());
+            assert_eq!(Ok(digest.clone()), digest.to_string().to_uppercase().parse());
+        }
+        assert_eq!(Ok(Digest(vec![0x00, 0xab, 0xff])), "00abFF".parse());
+        assert_eq!(Err(ParseDigestError::Empty), "".parse::<Digest>());
+        assert_eq!(Err(ParseDigestError::OddLength), "abc".parse::<Digest>());
+        assert_eq!(Err(ParseDigestError::BadDigit(2)), "00g0".parse::<Digest>());
+        assert_eq!("bad hex digit at position 2", "0x00".parse::<Digest>().unwrap_err().to_string());
+    }
+
+    #[test]
+    fn many() {
+        assert_eq!(Vec::<Digest>::new(), knot_hash_many(&[]));
+        assert_eq!(vec![knot_hash("AoC 2017")], knot_hash_many(&["AoC 2017"]));
+
+        let keys: Vec<String> = (0..1000).map(|i| format!("flqrgnkx-{}", i)).collect();
+        let keys: Vec<&str> = keys.iter().map(|k| k.as_str()).collect();
+        let expected: Vec<Digest> = keys.iter().map(|k| knot_hash(k)).collect();
+        assert_eq!(expected, knot_hash_many(&keys));
+        assert_eq!(expected[..37], knot_hash_many(&keys[..37])[..]);
+        for n in [15, 16, 17, 33] {
+            assert_eq!(expected[..n], knot_hash_many(&keys[..n])[..]);
+        }
+
+        // keys of very different lengths share each thread's buffers
+        let keys: Vec<String> = (0..200).map(|i| "x".repeat(i * 37 % 301)).collect();
+        let keys: Vec<&str> = keys.iter().map(|k| k.as_str()).collect();
+        let expected: Vec<Digest> = keys.iter().map(|k| knot_hash(k)).collect();
+        assert_eq!(expected, knot_hash_many(&keys));
+    }
+
     #[test]
     fn real_input() {
         let input = include_str!("../inputs/day10.txt");
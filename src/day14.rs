@@ -1,12 +1,13 @@
-use day10::knot_hash;
+use day10::knot_hash_many;
 
 fn solve(input: &str) -> (u32, u32) {
 
     let mut bits = 0;
     let mut grid: Vec<Vec<bool>> = Vec::new();
-    for i in 0..128 {
+    let keys: Vec<String> = (0..128).map(|i| format!("{}-{}", input, i)).collect();
+    let keys: Vec<&str> = keys.iter().map(|k| k.as_str()).collect();
+    for h in knot_hash_many(&keys) {
         let mut row = String::new();
-        let h = knot_hash(&format!("{}-{}", input, i));
         for n in h.iter() {
             bits += n.count_ones();
             row += &format!("{:08b}", n);
@@ -0,0 +1,330 @@
+//! Integer expressions shared by the small languages in day05 and day08.
+//!
+//! Expressions are integers and variables combined with `+ - * / %`,
+//! comparisons, `&& || !` and, where enabled, `if c then a else b`.
+//! Comparisons and logic yield 1 or 0, and any nonzero value counts as
+//! true. Arithmetic is checked, so overflow is an error rather than a wrap.
+
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Token<'a> {
+    Num(i64),
+    Ident(&'a str),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+}
+
+const OPS: [&str; 18] = ["&&", "||", "<=", ">=", "==", "!=",
+                         "<", ">", "+", "-", "*", "/", "%", "!",
+                         "(", ")", ",", "="];
+
+/// A syntax error at byte offset `pos`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub pos: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at column {}: {}", self.pos + 1, self.msg)
+    }
+}
+
+pub fn error<T>(pos: usize, msg: &str) -> Result<T, ParseError> {
+    Err(ParseError { pos, msg: msg.to_string() })
+}
+
+pub fn lex(s: &str) -> Result<Vec<(usize, Token<'_>)>, ParseError> {
+    let bytes = s.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        if c.is_ascii_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < bytes.len() && bytes[i].is_ascii_digit() {
+                i += 1;
+            }
+            match s[start..i].parse() {
+                Ok(n) => tokens.push((start, Token::Num(n))),
+                Err(_) => return error(start, "number too large"),
+            }
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            let start = i;
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            tokens.push((start, Token::Ident(&s[start..i])));
+        } else {
+            let op = OPS.iter().find(|op| s[i..].starts_with(*op));
+            let tok = match op {
+                Some(&"(") => Token::LParen,
+                Some(&")") => Token::RParen,
+                Some(&",") => Token::Comma,
+                Some(&"=") => return error(i, "use '==' for comparison"),
+                Some(op) => Token::Op(op),
+                None => return error(i, &format!("unexpected '{}'", &s[i..].chars().next().unwrap())),
+            };
+            tokens.push((i, tok));
+            i += op.unwrap().len();
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Num(i64),
+    Var(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+// Binary operators from loosest to tightest binding.
+const LEVELS: [&[&str]; 5] = [
+    &["||"],
+    &["&&"],
+    &["<", "<=", "==", "!=", ">=", ">"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+/// Recursive-descent parser over a lexed string. Languages built on it
+/// parse their own statements with `peek` and `advance`, and call `expr`
+/// wherever an expression may appear.
+pub struct Parser<'a> {
+    tokens: Vec<(usize, Token<'a>)>,
+    i: usize,
+    end: usize,
+    conditionals: bool,
+    vars: Option<&'static [&'static str]>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(s: &'a str) -> Result<Parser<'a>, ParseError> {
+        Ok(Parser { tokens: lex(s)?, i: 0, end: s.len(), conditionals: false, vars: None })
+    }
+
+    /// Accept `if c then a else b`. The three keywords can then no longer
+    /// be used as variable names.
+    pub fn conditionals(mut self, on: bool) -> Parser<'a> {
+        self.conditionals = on;
+        self
+    }
+
+    /// Reject any variable not in `names`.
+    pub fn vars(mut self, names: &'static [&'static str]) -> Parser<'a> {
+        self.vars = Some(names);
+        self
+    }
+
+    pub fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.i).map(|&(_, t)| t)
+    }
+
+    pub fn peek_at(&self, k: usize) -> Option<Token<'a>> {
+        self.tokens.get(self.i + k).map(|&(_, t)| t)
+    }
+
+    pub fn advance(&mut self, n: usize) {
+        self.i += n;
+    }
+
+    /// Byte offset of the next token, or the end of the input.
+    pub fn pos(&self) -> usize {
+        self.tokens.get(self.i).map_or(self.end, |&(p, _)| p)
+    }
+
+    pub fn expect(&mut self, tok: Token, what: &str) -> Result<(), ParseError> {
+        if self.peek() == Some(tok) {
+            self.i += 1;
+            Ok(())
+        } else {
+            error(self.pos(), &format!("expected {}", what))
+        }
+    }
+
+    /// Fail if anything is left after the `what` just parsed.
+    pub fn finish(&self, what: &str) -> Result<(), ParseError> {
+        if self.i < self.tokens.len() {
+            return error(self.pos(), &format!("unexpected input after {}", what));
+        }
+        Ok(())
+    }
+
+    fn keyword(&self, tok: Option<Token>) -> Option<&'static str> {
+        match tok {
+            Some(Token::Ident(w)) if self.conditionals => {
+                ["if", "then", "else"].iter().find(|&&k| k == w).cloned()
+            },
+            _ => None,
+        }
+    }
+
+    pub fn expr(&mut self) -> Result<Expr, ParseError> {
+        if self.keyword(self.peek()) == Some("if") {
+            self.i += 1;
+            let cond = self.expr()?;
+            self.expect(Token::Ident("then"), "'then'")?;
+            let yes = self.expr()?;
+            self.expect(Token::Ident("else"), "'else'")?;
+            let no = self.expr()?;
+            Ok(Expr::If(Box::new(cond), Box::new(yes), Box::new(no)))
+        } else {
+            self.binary(0)
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, ParseError> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(Token::Op(op)) = self.peek() {
+            if !LEVELS[level].contains(&op) {
+                break;
+            }
+            self.i += 1;
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        match self.peek() {
+            Some(Token::Op(op)) if op == "-" || op == "!" => {
+                self.i += 1;
+                Ok(Expr::Unary(op, Box::new(self.unary()?)))
+            },
+            Some(Token::Num(n)) => { self.i += 1; Ok(Expr::Num(n)) },
+            tok @ Some(Token::Ident(_)) if self.keyword(tok) == Some("if") => self.expr(),
+            tok @ Some(Token::Ident(_)) if self.keyword(tok).is_some() =>
+                error(self.pos(), "expected a value"),
+            Some(Token::Ident(v)) => {
+                if self.vars.is_some_and(|vars| !vars.contains(&v)) {
+                    return error(self.pos(), &format!("unknown word '{}'", v));
+                }
+                self.i += 1;
+                Ok(Expr::Var(v.to_string()))
+            },
+            Some(Token::LParen) => {
+                self.i += 1;
+                let e = self.expr()?;
+                self.expect(Token::RParen, "')'")?;
+                Ok(e)
+            },
+            _ => error(self.pos(), "expected a value"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    DivideByZero,
+    Overflow,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EvalError::DivideByZero => write!(f, "division by zero"),
+            EvalError::Overflow => write!(f, "arithmetic overflow"),
+        }
+    }
+}
+
+impl Expr {
+    /// Evaluate with `var` giving the value of each variable.
+    pub fn eval<F: Fn(&str) -> i64>(&self, var: &F) -> Result<i64, EvalError> {
+        let ovf = |x: Option<i64>| x.ok_or(EvalError::Overflow);
+        match *self {
+            Expr::Num(n) => Ok(n),
+            Expr::Var(ref v) => Ok(var(v)),
+            Expr::Unary(op, ref e) => {
+                let x = e.eval(var)?;
+                if op == "-" { ovf(x.checked_neg()) } else { Ok((x == 0) as i64) }
+            },
+            Expr::If(ref c, ref yes, ref no) =>
+                if c.eval(var)? != 0 { yes.eval(var) } else { no.eval(var) },
+            // short-circuit, so `j != 0 && 10 / j > 1` is safe
+            Expr::Binary("&&", ref a, ref b) =>
+                Ok((a.eval(var)? != 0 && b.eval(var)? != 0) as i64),
+            Expr::Binary("||", ref a, ref b) =>
+                Ok((a.eval(var)? != 0 || b.eval(var)? != 0) as i64),
+            Expr::Binary(op, ref a, ref b) => {
+                let (x, y) = (a.eval(var)?, b.eval(var)?);
+                match op {
+                    "+" => ovf(x.checked_add(y)),
+                    "-" => ovf(x.checked_sub(y)),
+                    "*" => ovf(x.checked_mul(y)),
+                    "/" | "%" if y == 0 => Err(EvalError::DivideByZero),
+                    "/" => ovf(x.checked_div(y)),
+                    "%" => ovf(x.checked_rem(y)),
+                    "<" => Ok((x < y) as i64),
+                    "<=" => Ok((x <= y) as i64),
+                    "==" => Ok((x == y) as i64),
+                    "!=" => Ok((x != y) as i64),
+                    ">=" => Ok((x >= y) as i64),
+                    ">" => Ok((x > y) as i64),
+                    _ => unreachable!("unknown operator {}", op),
+                }
+            },
+        }
+    }
+
+    /// Every variable the expression mentions, in order, with repeats.
+    pub fn vars<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match *self {
+            Expr::Num(_) => {},
+            Expr::Var(ref v) => out.push(v),
+            Expr::Unary(_, ref e) => e.vars(out),
+            Expr::Binary(_, ref a, ref b) => { a.vars(out); b.vars(out); },
+            Expr::If(ref c, ref yes, ref no) => { c.vars(out); yes.vars(out); no.vars(out); },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<Expr, ParseError> {
+        let mut p = Parser::new(s)?.conditionals(true);
+        let e = p.expr()?;
+        p.finish("expression")?;
+        Ok(e)
+    }
+
+    #[test]
+    fn precedence() {
+        let eval = |s: &str| parse(s).unwrap().eval(&|v| if v == "x" { 3 } else { 0 });
+        assert_eq!(Ok(7), eval("1 + 2 * 3"));
+        assert_eq!(Ok(1), eval("1 < 2 == 1"));
+        assert_eq!(Ok(1), eval("0 && 1 || 1"));
+        assert_eq!(Ok(-9), eval("-x * x"));
+        assert_eq!(Ok(10), eval("if x > 2 then 10 else 20"));
+        assert_eq!(Ok(0), eval("y"));
+    }
+
+    #[test]
+    fn keywords_and_vars() {
+        let mut p = Parser::new("if + then").unwrap();
+        let e = p.expr().unwrap();
+        let mut vars = Vec::new();
+        e.vars(&mut vars);
+        assert_eq!(vars, vec!["if", "then"]);
+
+        assert_eq!(Err(ParseError { pos: 4, msg: "expected a value".to_string() }),
+                   parse("1 + then"));
+        let mut p = Parser::new("j + k").unwrap().vars(&["j"]);
+        assert_eq!("at column 5: unknown word 'k'", p.expr().unwrap_err().to_string());
+    }
+}
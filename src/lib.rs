@@ -1,3 +1,6 @@
+extern crate unicode_normalization;
+extern crate unicode_segmentation;
+
 pub mod day01;
 pub mod day02;
 pub mod day03;
@@ -23,3 +26,6 @@ pub mod day22;
 pub mod day23;
 pub mod day24;
 pub mod day25;
+
+pub mod expr;
+pub mod rng;
@@ -0,0 +1,43 @@
+//! Small deterministic generators for benchmarks and tests, so that
+//! pseudo-random inputs are the same on every run.
+
+/// Marsaglia's xorshift32. Fast and reproducible, not statistically strong.
+#[derive(Debug, Clone)]
+pub struct XorShift32(u32);
+
+impl XorShift32 {
+    /// Zero is a fixed point of the generator, so `seed` must be non-zero.
+    pub fn new(seed: u32) -> XorShift32 {
+        assert!(seed != 0, "xorshift seed must be non-zero");
+        XorShift32(seed)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+}
+
+/// The splitmix64 finaliser: a bijection on u64 where every input bit
+/// affects every output bit.
+pub fn splitmix64(z: u64) -> u64 {
+    let mut z = z.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_values() {
+        let mut rng = XorShift32::new(1);
+        assert_eq!(270369, rng.next_u32());
+        assert_eq!(67634689, rng.next_u32());
+        assert_eq!(0xe220a8397b1dcdaf, splitmix64(0));
+    }
+}